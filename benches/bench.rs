@@ -0,0 +1,160 @@
+//! Micro benchmarks, run with `cargo bench`. Each prints the mean time per
+//! iteration, so compare numbers from the same machine only.
+
+use std::{
+    fmt::Write,
+    hint::black_box,
+    time::{Duration, Instant},
+};
+
+use write_json::{Formatter, PreparedKey, PreparedKeys};
+
+fn main() {
+    let whole: Vec<f64> = (0..10_000).map(|i| (i * 7919) as f64).collect();
+    bench("number: whole f64s", || {
+        let mut buf = String::new();
+        let mut arr = write_json::array(&mut buf);
+        for &n in &whole {
+            arr.number(n);
+        }
+        drop(arr);
+        buf
+    });
+    bench("number: whole f64s via Display", || {
+        let mut buf = String::new();
+        buf.push('[');
+        for (i, &n) in whole.iter().enumerate() {
+            if i > 0 {
+                buf.push(',');
+            }
+            let _ = write!(buf, "{n}");
+        }
+        buf.push(']');
+        buf
+    });
+
+    let words: Vec<Vec<u8>> = (0..10_000)
+        .map(|i| format!("word number {i}, plain ASCII").into_bytes())
+        .collect();
+    bench("string bytes: checked", || {
+        let mut buf = String::new();
+        let mut arr = write_json::array(&mut buf);
+        for word in &words {
+            let _ = arr.try_string_bytes(word);
+        }
+        drop(arr);
+        buf
+    });
+    bench("string bytes: unchecked", || {
+        let mut buf = String::new();
+        let mut arr = write_json::array(&mut buf);
+        for word in &words {
+            // SAFETY: made from `String`s above.
+            unsafe { arr.string_bytes_unchecked(word) };
+        }
+        drop(arr);
+        buf
+    });
+
+    let rows: Vec<(f64, f64)> = (0..10_000).map(|i| (i as f64, 0.5)).collect();
+    bench("keys: plain", || {
+        let mut buf = String::new();
+        let mut arr = write_json::array(&mut buf);
+        for &(id, score) in &rows {
+            arr.object().number("id", id).number("score", score);
+        }
+        drop(arr);
+        buf
+    });
+    let (id_key, score_key) = (PreparedKey::new("id"), PreparedKey::new("score"));
+    bench("keys: prepared", || {
+        let mut buf = String::new();
+        let mut arr = write_json::array(&mut buf);
+        for &(id, score) in &rows {
+            arr.object()
+                .field_prepared(&id_key, id, write_json::number)
+                .field_prepared(&score_key, score, write_json::number);
+        }
+        drop(arr);
+        buf
+    });
+    let keys = [id_key.clone(), score_key.clone()];
+    bench("keys: objects_with_keys", || {
+        let mut buf = String::new();
+        write_json::array(&mut buf).objects_with_keys(&keys, &rows, |buf, row, i| {
+            write_json::number(buf, if i == 0 { row.0 } else { row.1 })
+        });
+        buf
+    });
+
+    bench("small objects: in order", || {
+        let mut buf = String::new();
+        let mut arr = write_json::array(&mut buf);
+        for &(id, score) in &rows {
+            let mut obj = arr.object();
+            obj.number("id", id).number("score", score);
+            obj.string("name", "Peter").bool("admin", false);
+        }
+        drop(arr);
+        buf
+    });
+    bench("small objects: sorted", || {
+        let mut buf = String::new();
+        for &(id, score) in &rows {
+            let mut obj = write_json::sorted_object(&mut buf);
+            obj.string("name", "Peter").bool("admin", false);
+            obj.number("score", score).number("id", id);
+        }
+        buf
+    });
+
+    let names = ["node_name\t", "child_nodes"];
+    bench("key lookups: plain", || {
+        let mut buf = String::new();
+        let mut arr = write_json::array(&mut buf);
+        for &(id, _) in &rows {
+            arr.object().number(names[0], id).null(names[1]);
+        }
+        drop(arr);
+        buf
+    });
+    let renamed = Formatter::new().rename_keys(write_json::camel_case);
+    bench("key lookups: plain, renamed", || {
+        let mut buf = String::new();
+        let mut arr = renamed.array(&mut buf);
+        for &(id, _) in &rows {
+            arr.object().number(names[0], id).null(names[1]);
+        }
+        drop(arr);
+        buf
+    });
+    let mut keys = PreparedKeys::new(renamed);
+    bench("key lookups: PreparedKeys, renamed", || {
+        let mut buf = String::new();
+        let mut arr = renamed.array(&mut buf);
+        for &(id, _) in &rows {
+            arr.object()
+                .field_prepared(keys.get(names[0]), id, write_json::number)
+                .field_prepared(keys.get(names[1]), (), |buf, ()| write_json::null(buf));
+        }
+        drop(arr);
+        buf
+    });
+}
+
+/// Runs `f` for about a second and prints the mean time per run, unless a
+/// filter is given (`cargo bench -- keys`) which `name` does not contain.
+fn bench<T, F: FnMut() -> T>(name: &str, mut f: F) {
+    let mut filters = std::env::args().skip(1).filter(|it| !it.starts_with("--"));
+    if filters.next().is_some_and(|filter| !name.contains(&filter)) {
+        return;
+    }
+    black_box(f());
+    let start = Instant::now();
+    let mut runs = 0u32;
+    while start.elapsed() < Duration::from_secs(1) {
+        black_box(f());
+        runs += 1;
+    }
+    println!("{name:<40} {:>10.2?}", start.elapsed() / runs);
+}
@@ -0,0 +1,50 @@
+//! Key renaming presets for [`Formatter::rename_keys`](crate::Formatter::rename_keys).
+
+use std::borrow::Cow;
+
+/// Converts `snake_case` to `camelCase`, so `user_id` becomes `userId`.
+///
+/// Leading underscores are kept, other underscores are dropped and
+/// capitalize the following character.
+pub fn camel_case(key: &str) -> Cow<'_, str> {
+    let body = key.trim_start_matches('_');
+    if !body.contains('_') {
+        return Cow::Borrowed(key);
+    }
+    let mut res = String::with_capacity(key.len());
+    res.push_str(&key[..key.len() - body.len()]);
+    let mut upper = false;
+    for c in body.chars() {
+        if c == '_' {
+            upper = true;
+        } else if upper {
+            upper = false;
+            res.extend(c.to_uppercase());
+        } else {
+            res.push(c);
+        }
+    }
+    Cow::Owned(res)
+}
+
+/// Converts `camelCase` to `snake_case`, so `userId` becomes `user_id`.
+///
+/// Every uppercase character is lowercased and, unless it starts the key,
+/// preceded by an underscore. Runs of capitals are not treated specially.
+pub fn snake_case(key: &str) -> Cow<'_, str> {
+    if !key.chars().any(char::is_uppercase) {
+        return Cow::Borrowed(key);
+    }
+    let mut res = String::with_capacity(key.len() + 4);
+    for c in key.chars() {
+        if c.is_uppercase() {
+            if !res.is_empty() {
+                res.push('_');
+            }
+            res.extend(c.to_lowercase());
+        } else {
+            res.push(c);
+        }
+    }
+    Cow::Owned(res)
+}
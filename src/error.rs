@@ -0,0 +1,71 @@
+use std::{fmt, io, str::Utf8Error};
+
+/// The error type of all fallible operations in this crate.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// Writing to an `io::Write` failed.
+    Io(io::Error),
+    /// A `NaN` or an infinity, which have no JSON representation, was
+    /// rejected.
+    NonFinite(f64),
+    /// Bytes which were expected to be UTF-8 were not.
+    InvalidUtf8(Utf8Error),
+    /// A fixed-size output ran out of space.
+    Overflow,
+    /// A string which was expected to hold a number did not.
+    InvalidNumber(String),
+    /// Nothing was written where a value was expected.
+    Empty,
+    /// Writing would have produced this many items, more than allowed.
+    TooLarge(usize),
+    /// The output took this many bytes, more than allowed.
+    SizeLimitExceeded(usize),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(err) => write!(f, "failed to write JSON: {err}"),
+            Error::NonFinite(value) => {
+                write!(
+                    f,
+                    "non-finite number cannot be represented in JSON: {value}"
+                )
+            }
+            Error::InvalidUtf8(err) => write!(f, "string is not valid UTF-8: {err}"),
+            Error::Overflow => write!(f, "output buffer is full"),
+            Error::InvalidNumber(input) => write!(f, "not a valid number: {input:?}"),
+            Error::Empty => write!(f, "no value was written"),
+            Error::TooLarge(len) => write!(f, "{len} items exceed the size limit"),
+            Error::SizeLimitExceeded(len) => write!(f, "{len} bytes exceed the size limit"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(err) => Some(err),
+            Error::InvalidUtf8(err) => Some(err),
+            Error::NonFinite(_)
+            | Error::Overflow
+            | Error::InvalidNumber(_)
+            | Error::Empty
+            | Error::TooLarge(_)
+            | Error::SizeLimitExceeded(_) => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Error {
+        Error::Io(err)
+    }
+}
+
+impl From<Utf8Error> for Error {
+    fn from(err: Utf8Error) -> Error {
+        Error::InvalidUtf8(err)
+    }
+}
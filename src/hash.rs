@@ -0,0 +1,164 @@
+use std::{hash::Hasher, mem::ManuallyDrop};
+
+use crate::{Array, Formatter, Object};
+
+/// An object which feeds the bytes it writes to a [`Hasher`] as it goes, so
+/// that the digest is ready as soon as the object is finished.
+///
+/// Bytes are hashed once each, right after every top-level field is written
+/// (nested containers are hashed on the next top-level write). Hence the
+/// hasher must produce the same result however the input is split across
+/// [`Hasher::write`] calls, which holds for [`Fnv1a`] and [`Crc32`]. Std's
+/// hashers make no such promise.
+pub struct HashingObject<'a, H: Hasher> {
+    obj: ManuallyDrop<Object<'a>>,
+    closed: bool,
+    hashed: usize,
+    hasher: H,
+}
+
+impl<'a, H: Hasher> HashingObject<'a, H> {
+    #[inline]
+    pub(crate) fn new(buf: &'a mut String, hasher: H) -> Self {
+        let hashed = buf.len();
+        HashingObject {
+            obj: ManuallyDrop::new(Object::new(buf, Formatter::new(), 0)),
+            closed: false,
+            hashed,
+            hasher,
+        }
+    }
+    #[inline]
+    fn obj(&mut self) -> &mut Object<'a> {
+        self.hash_pending();
+        &mut self.obj
+    }
+    #[inline]
+    fn hash_pending(&mut self) -> &mut Self {
+        self.hasher.write(&self.obj.buf.as_bytes()[self.hashed..]);
+        self.hashed = self.obj.buf.len();
+        self
+    }
+
+    #[inline]
+    pub fn null<K: AsRef<str>>(&mut self, key: K) -> &mut Self {
+        self.obj().null(key);
+        self.hash_pending()
+    }
+    #[inline]
+    pub fn bool<K: AsRef<str>>(&mut self, key: K, value: bool) -> &mut Self {
+        self.obj().bool(key, value);
+        self.hash_pending()
+    }
+    #[inline]
+    pub fn number<K: AsRef<str>>(&mut self, key: K, value: f64) -> &mut Self {
+        self.obj().number(key, value);
+        self.hash_pending()
+    }
+    #[inline]
+    pub fn string<K: AsRef<str>>(&mut self, key: K, value: &str) -> &mut Self {
+        self.obj().string(key, value);
+        self.hash_pending()
+    }
+    #[inline]
+    pub fn object<K: AsRef<str>>(&mut self, key: K) -> Object<'_> {
+        self.obj().object(key)
+    }
+    #[inline]
+    pub fn array<K: AsRef<str>>(&mut self, key: K) -> Array<'_> {
+        self.obj().array(key)
+    }
+
+    /// Writes the closing `}` and returns the digest of everything written
+    /// by this object.
+    #[inline]
+    pub fn finish(mut self) -> u64 {
+        self.close();
+        self.hasher.finish()
+    }
+    #[inline]
+    fn close(&mut self) {
+        if !self.closed {
+            self.closed = true;
+            self.obj.close();
+            self.hash_pending();
+        }
+    }
+}
+
+impl<H: Hasher> Drop for HashingObject<'_, H> {
+    fn drop(&mut self) {
+        self.close()
+    }
+}
+
+/// The 64-bit FNV-1a hash, a simple non-cryptographic hash which is fine to
+/// use with [`HashingObject`].
+#[derive(Debug, Clone, Copy)]
+pub struct Fnv1a(u64);
+
+impl Default for Fnv1a {
+    #[inline]
+    fn default() -> Self {
+        Fnv1a(0xcbf29ce484222325)
+    }
+}
+
+impl Hasher for Fnv1a {
+    #[inline]
+    fn write(&mut self, bytes: &[u8]) {
+        for &b in bytes {
+            self.0 ^= b as u64;
+            self.0 = self.0.wrapping_mul(0x100000001b3);
+        }
+    }
+    #[inline]
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+/// The CRC-32 checksum used by zlib, PNG and Ethernet (IEEE 802.3), for
+/// framed protocols. [`Hasher::finish`] returns it in the low 32 bits.
+#[derive(Debug, Clone, Copy)]
+pub struct Crc32(u32);
+
+impl Default for Crc32 {
+    #[inline]
+    fn default() -> Self {
+        Crc32(!0)
+    }
+}
+
+impl Hasher for Crc32 {
+    #[inline]
+    fn write(&mut self, bytes: &[u8]) {
+        for &b in bytes {
+            self.0 = CRC32_TABLE[usize::from(self.0 as u8 ^ b)] ^ (self.0 >> 8);
+        }
+    }
+    #[inline]
+    fn finish(&self) -> u64 {
+        u64::from(!self.0)
+    }
+}
+
+const CRC32_TABLE: [u32; 256] = {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xEDB88320
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
@@ -19,213 +19,2882 @@
 //!     r#"{"name":"Peter","favorite number":92,"films":["Drowning By Numbers","A Zed & Two Noughts"],"suitcase":null}"#
 //! )
 //! ```
+//!
+//! # Borrowing
+//!
+//! A container borrows the buffer mutably until it is dropped and writes its
+//! closing bracket, so nothing else can write to (or read) the buffer in the
+//! meantime:
+//!
+//! ```compile_fail
+//! let mut buf = String::new();
+//! let mut obj = write_json::object(&mut buf);
+//! write_json::string(&mut buf, "oops");
+//! obj.null("suitcase");
+//! ```
+//!
+//! ```compile_fail
+//! let mut buf = String::new();
+//! let obj = write_json::object(&mut buf);
+//! println!("{buf}");
+//! drop(obj);
+//! ```
+//!
+//! In the same way, a nested container borrows its parent, so the parent can
+//! only be continued once the child is done:
+//!
+//! ```compile_fail
+//! let mut buf = String::new();
+//! let mut obj = write_json::object(&mut buf);
+//! let mut films = obj.array("films");
+//! obj.null("suitcase");
+//! films.string("Drowning By Numbers");
+//! ```
+//!
+//! A container can't outlive its buffer:
+//!
+//! ```compile_fail
+//! fn leak() -> write_json::Object<'static> {
+//!     let mut buf = String::new();
+//!     write_json::object(&mut buf)
+//! }
+//! ```
+//!
+//! And arrays have no keys, while objects require them:
+//!
+//! ```compile_fail
+//! let mut buf = String::new();
+//! write_json::array(&mut buf).string("name", "Peter");
+//! ```
+//!
+//! ```compile_fail
+//! let mut buf = String::new();
+//! write_json::object(&mut buf).string("Peter");
+//! ```
+//!
+//! # Reusing Buffers
+//!
+//! Everything is appended to the buffer, which is never shrunk (only
+//! [`StreamingObject`] clears it, which keeps the capacity), so a `String`
+//! taken from a pool keeps its allocation. Clear it before reuse, and use
+//! [`object_reuse`] or [`array_reuse`] to catch a forgotten `clear` in debug
+//! builds. [`with_buffer`] does the clearing itself:
+//!
+//! ```
+//! let mut buf = String::with_capacity(1024);
+//! for id in 0..3 {
+//!     let json = write_json::with_buffer(&mut buf, |buf| {
+//!         write_json::object(buf).number("id", id as f64);
+//!     });
+//!     assert!(json.starts_with(r#"{"id":"#));
+//! }
+//! ```
+//!
+//! The returned `&str` borrows the buffer, so the compiler makes sure the
+//! output is used before the next document overwrites it.
+
+mod case;
+mod error;
+mod hash;
+mod nested;
+mod sorted;
+#[cfg(feature = "stats")]
+mod stats;
+mod stream;
+mod text;
+mod value;
+
+use std::{
+    borrow::Cow,
+    cmp::Ordering,
+    fmt,
+    hash::Hasher,
+    net::IpAddr,
+    ops::{ControlFlow, Range, RangeInclusive},
+    time::Duration,
+};
+
+pub use crate::{
+    case::{camel_case, snake_case},
+    error::Error,
+    hash::{Crc32, Fnv1a, HashingObject},
+    nested::NestedObject,
+    sorted::{SortedArray, SortedObject},
+    stream::{ChunkedArray, JsonLines, StreamingArray, StreamingObject},
+    text::{array_elements, minify, prune_nulls},
+    value::Value,
+};
+
+#[cfg(feature = "stats")]
+pub use crate::stats::{escape_stats, reset_escape_stats, EscapeStats};
+
+/// The flush threshold of [`streaming_object`] and [`streaming_array`] when
+/// none is given, in bytes, which keeps the buffer small without flushing
+/// for every field.
+pub const FLUSH_THRESHOLD: usize = 8 * 1024;
 
 #[inline]
 pub fn null(buf: &mut String) {
-    encode_null(buf, ());
+    encode_null(buf, &Formatter::new(), ());
 }
 #[inline]
 pub fn bool(buf: &mut String, value: bool) {
-    encode_bool(buf, value);
+    encode_bool(buf, &Formatter::new(), value);
 }
 #[inline]
 pub fn number(buf: &mut String, number: f64) {
-    encode_number(buf, number);
+    encode_number(buf, &Formatter::new(), number);
+}
+/// Like [`number`], but takes any type which converts to `f64` exactly,
+/// like `i32`, `u16` or `f32`. Note that an `f32` keeps all the digits of its
+/// exact value, so `0.1_f32` is written as `0.10000000149011612`.
+#[inline]
+pub fn number_from<N: Into<f64>>(buf: &mut String, number: N) {
+    encode_number(buf, &Formatter::new(), number.into());
+}
+/// Like [`number`], but refuses to write `NaN` and infinities, which have no
+/// JSON representation.
+#[inline]
+pub fn try_number(buf: &mut String, number: f64) -> Result<(), Error> {
+    check_finite(number)?;
+    encode_number(buf, &Formatter::new(), number);
+    Ok(())
 }
 #[inline]
 pub fn string(buf: &mut String, string: &str) {
-    encode_str(buf, string);
+    Formatter::new().string(buf, string);
+}
+/// Writes a value whose type is only known at runtime.
+#[inline]
+pub fn write_value(buf: &mut String, value: &Value) {
+    Formatter::new().value(buf, value);
+}
+/// Writes `value` with repeated strings stored once, see
+/// [`Formatter::value_dictionary`].
+#[inline]
+pub fn write_value_dictionary(buf: &mut String, value: &Value) {
+    Formatter::new().value_dictionary(buf, value);
+}
+/// Writes the characters produced by `chars` as a string.
+#[inline]
+pub fn string_chars<I: IntoIterator<Item = char>>(buf: &mut String, chars: I) {
+    Formatter::new().string_chars(buf, chars);
+}
+/// Whether writing `string` would escape any of its characters, for the
+/// default [`Formatter`].
+#[inline]
+pub fn needs_escaping(string: &str) -> bool {
+    Formatter::new().needs_escaping(string)
+}
+/// Escapes `string` into `dst` without allocating, see
+/// [`Formatter::escape_into_slice`].
+#[inline]
+pub fn escape_into_slice(dst: &mut [u8], string: &str) -> Result<usize, Error> {
+    Formatter::new().escape_into_slice(dst, string)
+}
+/// Writes a number given as text, allowing `_` between digits as a separator,
+/// so `"1_000"` is written as `1000`. Fails if the result is not a JSON number.
+#[inline]
+pub fn number_str_relaxed(buf: &mut String, number: &str) -> Result<(), Error> {
+    buf.push_str(&strip_separators(number)?);
+    Ok(())
+}
+/// Writes `duration` as an ISO 8601 string, see [`Object::duration_iso8601`].
+#[inline]
+pub fn duration_iso8601(buf: &mut String, duration: Duration) {
+    encode_duration_iso8601(buf, &Formatter::new(), duration);
+}
+/// Writes `bits` packed into a hex string, see [`Object::bitset`].
+#[inline]
+pub fn bitset(buf: &mut String, bits: &[bool]) {
+    encode_bitset(buf, &Formatter::new(), bits);
+}
+/// Writes `numbers` run-length encoded, which is not plain JSON, see
+/// [`Object::run_length`].
+#[inline]
+pub fn run_length(buf: &mut String, numbers: &[f64]) {
+    encode_run_length(buf, &Formatter::new(), numbers);
+}
+/// Writes `bytes` as a string if they are valid UTF-8, and nothing otherwise.
+#[inline]
+pub fn try_string_bytes(buf: &mut String, bytes: &[u8]) -> Result<(), Error> {
+    Formatter::new().string(buf, std::str::from_utf8(bytes)?);
+    Ok(())
+}
+/// Writes `bytes` as a string without checking that they are valid UTF-8.
+///
+/// # Safety
+///
+/// `bytes` must be valid UTF-8, as if checked by [`std::str::from_utf8`].
+#[inline]
+pub unsafe fn string_bytes_unchecked(buf: &mut String, bytes: &[u8]) {
+    Formatter::new().string(buf, std::str::from_utf8_unchecked(bytes));
 }
+/// Starts an object. Fields are written in the order they are added, with
+/// duplicates kept; use [`sorted_object`] to order them by key instead.
 #[inline]
 pub fn object(buf: &mut String) -> Object<'_> {
-    Object::new(buf)
+    Formatter::new().object(buf)
 }
 #[inline]
 pub fn array(buf: &mut String) -> Array<'_> {
-    Array::new(buf)
+    Formatter::new().array(buf)
+}
+/// Like [`object`], but asserts in debug builds that `buf` is empty, for
+/// buffers which are reused.
+#[inline]
+#[track_caller]
+pub fn object_reuse(buf: &mut String) -> Object<'_> {
+    debug_assert!(buf.is_empty(), "reused buffer is not empty");
+    object(buf)
+}
+/// Like [`array()`], but asserts in debug builds that `buf` is empty, for
+/// buffers which are reused.
+#[inline]
+#[track_caller]
+pub fn array_reuse(buf: &mut String) -> Array<'_> {
+    debug_assert!(buf.is_empty(), "reused buffer is not empty");
+    array(buf)
+}
+/// Clears `buf`, lets `write` write a document to it, and returns the result.
+#[inline]
+pub fn with_buffer<F: FnOnce(&mut String)>(buf: &mut String, write: F) -> &str {
+    buf.clear();
+    write(buf);
+    buf
+}
+/// Writes an array with an object for every item of `items`, filled in by
+/// `f`.
+#[inline]
+pub fn array_of_objects<T, I, F>(buf: &mut String, items: I, mut f: F)
+where
+    I: IntoIterator<Item = T>,
+    F: FnMut(&mut Object<'_>, &T),
+{
+    let mut arr = array(buf);
+    for item in items {
+        f(&mut arr.object(), &item);
+    }
+}
+/// Writes an object with an array for every `(key, items)` group of
+/// `groups`, with an element for every item written by `f`. Empty groups
+/// are written as `[]`.
+#[inline]
+pub fn object_of_arrays<K, G, T, I, F>(buf: &mut String, groups: I, mut f: F)
+where
+    K: AsRef<str>,
+    G: IntoIterator<Item = T>,
+    I: IntoIterator<Item = (K, G)>,
+    F: FnMut(&mut Array<'_>, &T),
+{
+    let mut obj = object(buf);
+    for (key, items) in groups {
+        let mut arr = obj.array(key);
+        for item in items {
+            f(&mut arr, &item);
+        }
+    }
+}
+/// Writes `pairs` as an object if their keys are unique, like `{"a":1}`, and
+/// as an array of `[key, value]` arrays otherwise, like `[["a",1],["a",2]]`,
+/// for multimap-like data.
+///
+/// Checking the keys takes a pass over `pairs` and a `HashSet` of the keys.
+#[inline]
+pub fn pairs<L, T, F>(buf: &mut String, pairs: &[(L, T)], mut enc: F)
+where
+    L: AsRef<str>,
+    F: FnMut(&mut String, &T),
+{
+    if keys_unique(pairs) {
+        let mut obj = object(buf);
+        for (key, value) in pairs {
+            obj.custom(key, value, &mut enc);
+        }
+    } else {
+        let mut arr = array(buf);
+        for (key, value) in pairs {
+            arr.array().string(key.as_ref()).custom(value, &mut enc);
+        }
+    }
+}
+#[inline]
+fn keys_unique<L: AsRef<str>, T>(pairs: &[(L, T)]) -> bool {
+    let mut seen = std::collections::HashSet::with_capacity(pairs.len());
+    pairs.iter().all(|(key, _)| seen.insert(key.as_ref()))
+}
+/// Continues an array left open by [`Array::suspend`] at the end of `buf`.
+#[inline]
+pub fn resume_array(buf: &mut String) -> Array<'_> {
+    Formatter::new().resume_array(buf)
+}
+/// Starts an object whose fields are written sorted by key once it is
+/// dropped.
+#[inline]
+pub fn sorted_object(buf: &mut String) -> SortedObject<'_> {
+    SortedObject::new(buf)
+}
+/// Like [`sorted_object`], but orders the keys with `cmp`.
+#[inline]
+pub fn sorted_object_by<'a, F>(buf: &'a mut String, cmp: F) -> SortedObject<'a>
+where
+    F: Fn(&str, &str) -> Ordering + 'a,
+{
+    SortedObject::with_cmp(buf, Box::new(cmp))
+}
+/// Starts an object whose fields are written in the order of `schema` once
+/// it is dropped, however they were added. Keys missing from `schema` go
+/// last, in the order they were written.
+#[inline]
+pub fn schema_object<'a>(buf: &'a mut String, schema: &'a [&'a str]) -> SortedObject<'a> {
+    let rank = |key: &str| {
+        schema
+            .iter()
+            .position(|it| *it == key)
+            .unwrap_or(schema.len())
+    };
+    sorted_object_by(buf, move |l, r| rank(l).cmp(&rank(r)))
+}
+/// Starts an array whose elements are written sorted by their serialized
+/// bytes once it is dropped.
+#[inline]
+pub fn sorted_array(buf: &mut String) -> SortedArray<'_> {
+    SortedArray::new(buf)
+}
+/// Starts an object which passes the buffer to `flush` and clears it every
+/// time it holds at least `threshold` bytes, [`FLUSH_THRESHOLD`] if `None`.
+///
+/// A lower threshold bounds memory more tightly but flushes more often.
+#[inline]
+pub fn streaming_object<F: FnMut(&str)>(
+    buf: &mut String,
+    threshold: Option<usize>,
+    flush: F,
+) -> StreamingObject<'_, F> {
+    StreamingObject::new(buf, threshold.unwrap_or(FLUSH_THRESHOLD), flush)
+}
+/// Starts an array which passes the buffer to `flush` and clears it every
+/// time it holds at least `threshold` bytes, [`FLUSH_THRESHOLD`] if `None`,
+/// and which `flush` can pause, see [`StreamingArray`].
+#[inline]
+pub fn streaming_array<F: FnMut(&str) -> ControlFlow<()>>(
+    buf: &mut String,
+    threshold: Option<usize>,
+    flush: F,
+) -> StreamingArray<'_, F> {
+    StreamingArray::new(buf, threshold.unwrap_or(FLUSH_THRESHOLD), flush)
+}
+/// Starts writing JSON Lines, one record per line, to `buf`.
+#[inline]
+pub fn json_lines(buf: &mut String) -> JsonLines<'_> {
+    JsonLines::new(buf)
+}
+/// Starts an array which owns its buffer, so that output can be taken out
+/// in chunks while it is being written.
+#[inline]
+pub fn chunked_array() -> ChunkedArray {
+    ChunkedArray::new()
+}
+/// Starts an object which hashes its output with `hasher` while it is being
+/// written.
+#[inline]
+pub fn hashing_object<H: Hasher>(buf: &mut String, hasher: H) -> HashingObject<'_, H> {
+    HashingObject::new(buf, hasher)
+}
+/// Runs `write`, which should write one value to `buf`, and writes `null` if
+/// it wrote nothing, so that the output is always a JSON document.
+#[inline]
+pub fn value_or_null<F: FnOnce(&mut String)>(buf: &mut String, write: F) {
+    let len = buf.len();
+    write(buf);
+    if buf.len() == len {
+        null(buf);
+    }
+}
+/// Like [`value_or_null`], but fails with [`Error::Empty`] if `write` wrote
+/// nothing.
+#[inline]
+pub fn require_value<F: FnOnce(&mut String)>(buf: &mut String, write: F) -> Result<(), Error> {
+    let len = buf.len();
+    write(buf);
+    if buf.len() == len {
+        return Err(Error::Empty);
+    }
+    Ok(())
+}
+/// Runs `write`, and fails with [`Error::SizeLimitExceeded`] if it appended
+/// more than `max_len` bytes to `buf`, in which case they are removed again.
+///
+/// This is a check of the finished output, not a budget: the length is only
+/// measured once `write` is done, so an oversized document is still built in
+/// full before it is rejected. [`Formatter::max_bytes`] stops writing
+/// instead.
+#[inline]
+pub fn check_size<F: FnOnce(&mut String)>(
+    buf: &mut String,
+    max_len: usize,
+    write: F,
+) -> Result<(), Error> {
+    let start = buf.len();
+    write(buf);
+    let len = buf.len() - start;
+    if len > max_len {
+        buf.truncate(start);
+        return Err(Error::SizeLimitExceeded(len));
+    }
+    Ok(())
+}
+/// Returns the document `write` appends to a fresh buffer, without spare
+/// capacity, for APIs which take ownership of it.
+#[inline]
+pub fn to_boxed_str<F: FnOnce(&mut String)>(write: F) -> Box<str> {
+    let mut buf = String::new();
+    write(&mut buf);
+    buf.into_boxed_str()
+}
+/// Returns the document `write` appends to a buffer, percent-encoded for use
+/// as a URL query parameter value.
+///
+/// Every byte except ASCII letters, digits, and `-._~` is encoded, so the
+/// result is safe anywhere in a URL.
+#[inline]
+pub fn to_query_param<F: FnOnce(&mut String)>(write: F) -> String {
+    let mut json = String::new();
+    write(&mut json);
+    let mut res = String::with_capacity(json.len() * 3);
+    for b in json.bytes() {
+        if b.is_ascii_alphanumeric() || matches!(b, b'-' | b'.' | b'_' | b'~') {
+            res.push(b as char);
+        } else {
+            res.push('%');
+            res.push(char::from(b"0123456789ABCDEF"[usize::from(b >> 4)]));
+            res.push(char::from(b"0123456789ABCDEF"[usize::from(b & 0xF)]));
+        }
+    }
+    res
+}
+
+/// Output options, inherited by all containers nested in the ones it starts.
+///
+/// The free functions and containers use [`Formatter::new`], which produces
+/// plain JSON.
+#[derive(Debug, Clone, Copy)]
+pub struct Formatter {
+    jsonp_safe: bool,
+    lowercase_hex: bool,
+    escape_c1: bool,
+    strip_controls: bool,
+    tab_width: Option<usize>,
+    always_fraction: bool,
+    non_finite_tokens: bool,
+    plus_sign: bool,
+    big_as_strings: bool,
+    float: Option<fn(&mut String, f64)>,
+    deny_empty_keys: bool,
+    max_entries: usize,
+    max_bytes: usize,
+    rename: Option<fn(&str) -> Cow<'_, str>>,
+    header: Option<(&'static str, &'static str)>,
+    check_balance: bool,
+    skip: Option<fn(&str) -> bool>,
+    pretty: bool,
+    indent: usize,
+    per_line: usize,
+    comment_every: usize,
+}
+
+impl Default for Formatter {
+    #[inline]
+    fn default() -> Formatter {
+        Formatter::new()
+    }
+}
+
+impl Formatter {
+    #[inline]
+    pub const fn new() -> Formatter {
+        Formatter {
+            jsonp_safe: false,
+            lowercase_hex: false,
+            escape_c1: false,
+            strip_controls: false,
+            tab_width: None,
+            always_fraction: false,
+            non_finite_tokens: false,
+            plus_sign: false,
+            big_as_strings: false,
+            float: None,
+            deny_empty_keys: false,
+            max_entries: usize::MAX,
+            max_bytes: usize::MAX,
+            rename: None,
+            header: None,
+            check_balance: false,
+            skip: None,
+            pretty: false,
+            indent: 0,
+            per_line: 1,
+            comment_every: 0,
+        }
+    }
+    /// Escapes `U+2028 LINE SEPARATOR` and `U+2029 PARAGRAPH SEPARATOR`,
+    /// which are valid in JSON strings but terminate JavaScript string
+    /// literals (before ES2019). Nothing else is affected, so the output can
+    /// be wrapped in a JSONP callback as is.
+    #[inline]
+    pub const fn jsonp_safe(mut self, yes: bool) -> Formatter {
+        self.jsonp_safe = yes;
+        self
+    }
+    /// Uses lowercase hex digits in `\uXXXX` escapes (`\u001f`, like
+    /// `serde_json`), instead of the default uppercase (`\u001F`).
+    #[inline]
+    pub const fn lowercase_hex(mut self, yes: bool) -> Formatter {
+        self.lowercase_hex = yes;
+        self
+    }
+    /// Escapes `DEL` (`U+007F`) and the C1 control characters
+    /// (`U+0080`-`U+009F`) as `\u` sequences. JSON doesn't require this, so by
+    /// default they are written as is, like `serde_json` does.
+    #[inline]
+    pub const fn escape_c1(mut self, yes: bool) -> Formatter {
+        self.escape_c1 = yes;
+        self
+    }
+    /// Drops control characters (`U+0000`-`U+001F`) from strings instead of
+    /// escaping them, except that tabs and line breaks become spaces. This
+    /// loses information, but suits log sinks which handle escapes poorly.
+    #[inline]
+    pub const fn strip_controls(mut self, yes: bool) -> Formatter {
+        self.strip_controls = yes;
+        self
+    }
+    /// Replaces every tab in strings, keys included, with `width` spaces
+    /// instead of escaping it as `\t`, for output that is diffed. This loses
+    /// information, and takes precedence over
+    /// [`strip_controls`](Formatter::strip_controls) for tabs.
+    #[inline]
+    pub const fn expand_tabs(mut self, width: usize) -> Formatter {
+        self.tab_width = Some(width);
+        self
+    }
+    /// Writes integral numbers with a fractional part, as `1.0` instead of
+    /// `1`, matching serializers like Python's `json` and `serde_json`. By
+    /// default, numbers are written the way JavaScript prints them.
+    #[inline]
+    pub const fn always_fraction(mut self, yes: bool) -> Formatter {
+        self.always_fraction = yes;
+        self
+    }
+    /// Writes `NaN` and infinities as the JavaScript tokens `NaN`, `Infinity`
+    /// and `-Infinity`.
+    ///
+    /// This is **not** JSON: only use it when the output is read back by a
+    /// parser which accepts these tokens, like Python's `json` or JSON5.
+    /// Without it, non-finite numbers are written in Rust's own format, which
+    /// no parser accepts; prefer the `try_number` methods to reject them.
+    #[inline]
+    pub const fn non_finite_tokens(mut self, yes: bool) -> Formatter {
+        self.non_finite_tokens = yes;
+        self
+    }
+    /// Writes non-negative numbers with a leading `+`, as `+5`, for the few
+    /// APIs which expect explicit signs.
+    ///
+    /// This is **not** JSON, and standard parsers reject it. Only finite
+    /// numbers get a sign, so `-0.0`, `NaN` and infinities are written as
+    /// usual, and [`Object::number_fixed`] and [`Object::number_sig`] are not
+    /// affected.
+    #[inline]
+    pub const fn plus_sign(mut self, yes: bool) -> Formatter {
+        self.plus_sign = yes;
+        self
+    }
+    /// Writes integral numbers beyond JavaScript's safe integer range,
+    /// `±(2^53 - 1)`, as strings, like `"9007199254740992"`, so that
+    /// JavaScript clients don't silently round them. Smaller numbers are
+    /// written as usual.
+    #[inline]
+    pub const fn big_as_strings(mut self, yes: bool) -> Formatter {
+        self.big_as_strings = yes;
+        self
+    }
+    /// Writes all numbers with `float` instead of the built-in formatting,
+    /// for example to use `ryu` or to round to a fixed number of digits.
+    ///
+    /// `float` must write a valid JSON number. It replaces
+    /// [`always_fraction`](Formatter::always_fraction),
+    /// [`non_finite_tokens`](Formatter::non_finite_tokens),
+    /// [`plus_sign`](Formatter::plus_sign) and
+    /// [`big_as_strings`](Formatter::big_as_strings), which are not applied on
+    /// top of it.
+    #[inline]
+    pub const fn float_format(mut self, float: fn(&mut String, f64)) -> Formatter {
+        self.float = Some(float);
+        self
+    }
+    /// Panics when an empty key is written, in debug builds only. `""` is a
+    /// valid key, but usually a sign of a bug.
+    #[inline]
+    pub const fn deny_empty_keys(mut self, yes: bool) -> Formatter {
+        self.deny_empty_keys = yes;
+        self
+    }
+    /// Writes at most `n` fields of each object and `n` elements of each
+    /// array, for data of untrusted size. Objects which had more fields end
+    /// with an extra `"_truncated":true` field; arrays are cut silently.
+    ///
+    /// The entries past the limit are still serialized, one at a time, and
+    /// then removed from the buffer, except for lazy ones like
+    /// [`Object::number_with`] and [`Object::placeholder`].
+    #[inline]
+    pub const fn max_entries(mut self, n: usize) -> Formatter {
+        self.max_entries = n;
+        self
+    }
+    /// Stops writing the entries of each object and array once it holds
+    /// more than `n` bytes, for data of untrusted size. The entry which
+    /// crossed the limit is removed with the ones after it, and objects end
+    /// with `"_truncated":true`, like with
+    /// [`max_entries`](Formatter::max_entries). So apart from the marker and
+    /// the closing bracket, a container stays within `n` bytes.
+    /// [`Object::finish`] and [`Array::finish`] report whether this
+    /// happened.
+    ///
+    /// The size is checked between entries, so an entry is serialized in
+    /// full before it is removed, though a nested container checks its own
+    /// entries too. Arrays continued with [`resume_array`] are not limited.
+    #[inline]
+    pub const fn max_bytes(mut self, n: usize) -> Formatter {
+        self.max_bytes = n;
+        self
+    }
+    /// Passes every key given as a string through `rename` before escaping
+    /// it, for example [`camel_case`] to write Rust field names for a
+    /// JavaScript API. The key prefix and keys written with
+    /// [`Object::entries`] are not renamed.
+    #[inline]
+    pub const fn rename_keys(mut self, rename: fn(&str) -> Cow<'_, str>) -> Formatter {
+        self.rename = Some(rename);
+        self
+    }
+    /// Starts every object, nested ones included, with a `key` field holding
+    /// `value`, like `"_v":1` for a schema version. `value` is written as
+    /// is, so it must be valid JSON.
+    ///
+    /// The header is written as given: it is not renamed, skipped, or
+    /// counted towards [`max_entries`](Formatter::max_entries).
+    #[inline]
+    pub const fn object_header(mut self, key: &'static str, value: &'static str) -> Formatter {
+        self.header = Some((key, value));
+        self
+    }
+    /// Checks that every nested container was closed when its parent is,
+    /// panicking otherwise, in debug builds only. This catches containers
+    /// whose `Drop` never ran, say because of `mem::forget`.
+    ///
+    /// The check scans everything the container wrote, so it makes writing
+    /// quadratic in the nesting depth.
+    #[inline]
+    pub const fn check_balance(mut self, yes: bool) -> Formatter {
+        self.check_balance = yes;
+        self
+    }
+    /// Leaves out every field whose key, given as a string, satisfies
+    /// `skip`, for example to redact secrets. Keys are tested before
+    /// renaming and without the key prefix.
+    ///
+    /// Like the entries past [`max_entries`](Formatter::max_entries), the
+    /// skipped fields are still serialized and then removed, except for
+    /// lazy ones like [`Object::number_with`] and [`Object::placeholder`].
+    #[inline]
+    pub const fn skip_fields(mut self, skip: fn(&str) -> bool) -> Formatter {
+        self.skip = Some(skip);
+        self
+    }
+    /// Puts every field and element on its own line, indented by `indent`
+    /// spaces per level of nesting, and adds a space after `:`.
+    #[inline]
+    pub const fn pretty(mut self, indent: usize) -> Formatter {
+        self.pretty = true;
+        self.indent = indent;
+        self
+    }
+    /// Puts every field and element on its own line without indenting it,
+    /// which is [`pretty(0)`](Formatter::pretty). Handy for grepping logs.
+    #[inline]
+    pub const fn flat(self) -> Formatter {
+        self.pretty(0)
+    }
+    /// In [`pretty`](Formatter::pretty) output, puts up to `n` array elements
+    /// on a line, separated by `, `, rather than one. Handy for long arrays of
+    /// numbers.
+    #[inline]
+    pub const fn elements_per_line(mut self, n: usize) -> Formatter {
+        self.per_line = if n == 0 { 1 } else { n };
+        self
+    }
+
+    /// In [`pretty`](Formatter::pretty) output, follows every `n` array
+    /// elements with a line comment giving their indices, like
+    /// `// items 0-99`, to help reading huge arrays.
+    ///
+    /// Comments are not JSON: only use this for JSONC readers like code
+    /// editors. Outside of pretty mode, and for `n` zero, it does nothing.
+    #[inline]
+    pub const fn index_comments(mut self, n: usize) -> Formatter {
+        self.comment_every = n;
+        self
+    }
+
+    #[inline]
+    pub fn number(&self, buf: &mut String, number: f64) {
+        encode_number(buf, self, number);
+    }
+    #[inline]
+    pub fn string(&self, buf: &mut String, string: &str) {
+        encode_str(buf, self, string);
+    }
+    #[inline]
+    pub fn string_chars<I: IntoIterator<Item = char>>(&self, buf: &mut String, chars: I) {
+        encode_chars(buf, self, chars);
+    }
+    /// Escapes `key` once, for use with [`Object::field_prepared`].
+    #[inline]
+    pub fn prepare_key(&self, key: &str) -> PreparedKey {
+        let key = self.renamed(key);
+        debug_assert!(!self.deny_empty_keys || !key.is_empty(), "empty object key");
+        let mut text = String::with_capacity(key.len() + 4);
+        text.push('"');
+        escape_str(&mut text, self, &key);
+        text.push_str(if self.pretty { "\": " } else { "\":" });
+        PreparedKey {
+            text,
+            key: key.into(),
+        }
+    }
+    /// Whether writing `string` with this formatter would escape any of its
+    /// characters.
+    #[inline]
+    pub fn needs_escaping(&self, string: &str) -> bool {
+        !is_plain_ascii(string)
+            && string.chars().any(|c| match c {
+                '\0'..='\x1F' | '"' | '\\' => true,
+                '\x7F'..='\u{9F}' => self.escape_c1,
+                '\u{2028}' | '\u{2029}' => self.jsonp_safe,
+                _ => false,
+            })
+    }
+    /// Writes the escaped contents of `string`, without the quotes, to the
+    /// start of `dst` and returns the number of bytes written.
+    ///
+    /// Fails with [`Error::Overflow`] if the result does not fit, in which
+    /// case the contents of `dst` are unspecified.
+    #[inline]
+    pub fn escape_into_slice(&self, dst: &mut [u8], string: &str) -> Result<usize, Error> {
+        let mut sink = Sink::Slice(SliceCursor {
+            dst,
+            len: 0,
+            overflow: false,
+        });
+        if is_plain_ascii(string) {
+            sink.push_str(string);
+        } else {
+            for c in string.chars() {
+                escape_char(&mut sink, self, c);
+                if sink.overflowed() {
+                    break;
+                }
+            }
+        }
+        match sink {
+            Sink::Slice(cursor) if !cursor.overflow => Ok(cursor.len),
+            _ => Err(Error::Overflow),
+        }
+    }
+    #[inline]
+    pub fn object<'a>(&self, buf: &'a mut String) -> Object<'a> {
+        Object::new(buf, *self, 0)
+    }
+    #[inline]
+    pub fn array<'a>(&self, buf: &'a mut String) -> Array<'a> {
+        Array::new(buf, *self, 0)
+    }
+    /// See [`resume_array`].
+    #[inline]
+    pub fn resume_array<'a>(&self, buf: &'a mut String) -> Array<'a> {
+        let len = if buf.ends_with('[') { 0 } else { 1 };
+        Array {
+            buf,
+            fmt: *self,
+            depth: 0,
+            len,
+            cut: None,
+            start: None,
+            last: 0,
+            exceeded: None,
+            ranges: None,
+        }
+    }
+
+    #[inline]
+    fn renamed<'k>(&self, key: &'k str) -> Cow<'k, str> {
+        match self.rename {
+            Some(rename) => rename(key),
+            None => Cow::Borrowed(key),
+        }
+    }
+    #[inline]
+    fn newline(&self, buf: &mut String, depth: usize) {
+        if self.pretty {
+            buf.push('\n');
+            buf.extend(std::iter::repeat_n(' ', self.indent * depth));
+        }
+    }
 }
 
+/// An object being written. Fields appear in the output exactly in the order
+/// they are written.
 pub struct Object<'a> {
     buf: &'a mut String,
+    fmt: Formatter,
+    depth: usize,
     first: bool,
+    prefix: &'a str,
+    len: usize,
+    /// Where the fields past [`Formatter::max_entries`] start.
+    cut: Option<usize>,
+    /// Where the opening `{` is.
+    start: usize,
+    /// Where the last field starts, for [`Formatter::max_bytes`].
+    last: usize,
+    /// The length which exceeded [`Formatter::max_bytes`].
+    exceeded: Option<usize>,
+    /// Where the field left out by [`Formatter::skip_fields`] starts, and
+    /// the `first` and `len` to go back to.
+    skipped: Option<(usize, bool, usize)>,
+    omitted: Option<&'a mut Vec<String>>,
+    ranges: Option<Recorder<'a>>,
 }
 
 impl<'a> Object<'a> {
     #[inline]
-    fn new(buf: &'a mut String) -> Self {
-        buf.push('{');
-        Object { buf, first: true }
+    fn new(buf: &'a mut String, fmt: Formatter, depth: usize) -> Self {
+        let start = buf.len();
+        buf.push('{');
+        let mut obj = Object {
+            buf,
+            fmt,
+            depth,
+            first: true,
+            prefix: "",
+            len: 0,
+            cut: None,
+            start,
+            last: 0,
+            exceeded: None,
+            skipped: None,
+            omitted: None,
+            ranges: None,
+        };
+        if let Some((key, value)) = fmt.header {
+            obj.write_key(|buf, fmt| escape_str(buf, fmt, key));
+            obj.buf.push_str(value);
+        }
+        obj
+    }
+    #[inline]
+    fn key(&mut self, key: &str) {
+        if self.skips(key) {
+            self.omit(key);
+            self.drop_skipped();
+            self.skipped = Some((self.buf.len(), self.first, self.len));
+        }
+        let key = self.fmt.renamed(key);
+        self.buf.reserve(self.prefix.len() + key.len() + 4);
+        self.key_with(|buf, fmt| escape_str(buf, fmt, &key));
+        if self.ranges.is_some() {
+            self.record(&format!("{}{key}", self.prefix));
+        }
+    }
+    /// Records the value which starts now under `key`.
+    #[inline]
+    fn record(&mut self, key: &str) {
+        if let Some(ranges) = &mut self.ranges {
+            ranges.open(self.buf.len(), key);
+        }
+    }
+    /// Truncates the buffer, and the ranges recorded in the removed part.
+    #[inline]
+    fn truncate(&mut self, len: usize) {
+        self.buf.truncate(len);
+        if let Some(ranges) = &mut self.ranges {
+            ranges.forget_from(len);
+        }
+    }
+    #[inline]
+    fn key_with<F: FnOnce(&mut String, &Formatter)>(&mut self, escape: F) {
+        self.count();
+        self.write_key(escape);
+    }
+    #[inline]
+    fn omit(&mut self, key: &str) {
+        if let Some(omitted) = &mut self.omitted {
+            omitted.push(key.to_string());
+        }
+    }
+    #[inline]
+    fn skips(&self, key: &str) -> bool {
+        self.fmt.skip.is_some_and(|skip| skip(key))
+    }
+    #[inline]
+    fn drop_skipped(&mut self) {
+        if let Some((start, first, len)) = self.skipped.take() {
+            self.truncate(start);
+            self.first = first;
+            self.len = len;
+            if self.cut == Some(start) {
+                self.cut = None;
+            }
+        }
+    }
+    #[inline]
+    fn count(&mut self) {
+        if let Some(ranges) = &mut self.ranges {
+            ranges.end(self.buf.len());
+        }
+        if self
+            .skipped
+            .is_some_and(|(start, ..)| start < self.buf.len())
+        {
+            self.drop_skipped();
+        }
+        self.check_bytes();
+        match self.cut {
+            Some(cut) => self.truncate(cut),
+            None if self.len == self.fmt.max_entries => self.cut = Some(self.buf.len()),
+            None => (),
+        }
+        self.last = self.buf.len();
+        self.len += 1;
+    }
+    /// Cuts the fields off from the last one if it took the object past
+    /// [`Formatter::max_bytes`].
+    #[inline]
+    fn check_bytes(&mut self) {
+        // Streaming objects clear the buffer, hence the saturation.
+        let len = self.buf.len().saturating_sub(self.start);
+        if self.cut.is_none() && self.len > 0 && len > self.fmt.max_bytes {
+            self.cut = Some(self.last);
+            self.exceeded = Some(len);
+        }
+    }
+    /// Whether a lazy field under `key` is left out, in which case it is
+    /// accounted for as if it was written and removed.
+    #[inline]
+    fn leaves_out(&mut self, key: &str) -> bool {
+        if self.skips(key) {
+            self.omit(key);
+            return true;
+        }
+        self.drop_skipped();
+        self.check_bytes();
+        if self.cut.is_some() || self.len >= self.fmt.max_entries {
+            if self.cut.is_none() {
+                self.cut = Some(self.buf.len());
+            }
+            self.len += 1;
+            return true;
+        }
+        false
+    }
+    #[inline]
+    fn separator(&mut self) {
+        if !self.first {
+            self.buf.push(',');
+        }
+        self.first = false;
+        self.fmt.newline(self.buf, self.depth + 1);
+    }
+    #[inline]
+    fn write_key<F: FnOnce(&mut String, &Formatter)>(&mut self, escape: F) {
+        self.separator();
+        self.buf.push('"');
+        let start = self.buf.len();
+        if !self.prefix.is_empty() {
+            escape_str(self.buf, &self.fmt, self.prefix);
+        }
+        escape(self.buf, &self.fmt);
+        debug_assert!(
+            !self.fmt.deny_empty_keys || self.buf.len() > start,
+            "empty object key"
+        );
+        self.buf
+            .push_str(if self.fmt.pretty { "\": " } else { "\":" });
+    }
+    #[inline]
+    fn close(&mut self) {
+        if let Some(ranges) = &mut self.ranges {
+            ranges.end(self.buf.len());
+        }
+        self.drop_skipped();
+        self.check_bytes();
+        if let Some(cut) = self.cut.take() {
+            self.truncate(cut);
+            // The header, if any, is never cut.
+            self.first = cut == self.start + 1;
+            self.prefix = "";
+            self.write_key(|buf, _| buf.push_str("_truncated"));
+            self.buf.push_str("true");
+        }
+        if !self.first {
+            self.fmt.newline(self.buf, self.depth);
+        }
+        debug_assert!(
+            !self.fmt.check_balance || text::unclosed(&self.buf[self.start..]) == 1,
+            "a container nested in this object was not closed"
+        );
+        self.buf.push('}');
+        if let Some(ranges) = &mut self.ranges {
+            ranges.close(self.buf.len());
+        }
+    }
+    #[inline]
+    fn field<T, F>(&mut self, key: &str, enc: F, value: T) -> &mut Self
+    where
+        F: FnOnce(&mut String, &Formatter, T),
+    {
+        self.key(key);
+        enc(self.buf, &self.fmt, value);
+        self
+    }
+
+    /// Writes the closing `}`, like dropping the object, and fails with
+    /// [`Error::SizeLimitExceeded`] if fields were cut off because of
+    /// [`Formatter::max_bytes`].
+    #[inline]
+    pub fn finish(mut self) -> Result<(), Error> {
+        self.close();
+        let exceeded = self.exceeded;
+        std::mem::forget(self);
+        exceeded.map_or(Ok(()), |len| Err(Error::SizeLimitExceeded(len)))
+    }
+    /// Prepends `prefix` to every key subsequently written to this object.
+    ///
+    /// Prefix and key are escaped together, as a single string. Nested
+    /// objects start without a prefix; call `set_key_prefix` on them to opt
+    /// in. An empty prefix turns prefixing off.
+    #[inline]
+    pub fn set_key_prefix(&mut self, prefix: &'a str) -> &mut Self {
+        self.prefix = prefix;
+        self
+    }
+    /// Appends to `omitted` the key of every field this object subsequently
+    /// leaves out, because of [`Formatter::skip_fields`] or because
+    /// [`Object::first_some`] or [`Object::object_if`] had nothing to write.
+    /// This is for debugging sparse output; nested objects don't record into
+    /// it.
+    #[inline]
+    pub fn record_omitted(&mut self, omitted: &'a mut Vec<String>) -> &mut Self {
+        self.omitted = Some(omitted);
+        self
+    }
+    /// Appends to `ranges` the JSON pointer and the byte range in the buffer
+    /// of every value this object subsequently writes, nested ones
+    /// included, starting with `""` for the object itself. This maps
+    /// positions in the output, like the ones in a parser's errors, back to
+    /// logical paths.
+    ///
+    /// Pointers are relative to this object, so call this right after
+    /// starting the document. Values left out by a [`Formatter`] limit are
+    /// not recorded, and neither are the fields of a [`NestedObject`].
+    #[inline]
+    pub fn record_ranges(&mut self, ranges: &'a mut Vec<(String, Range<usize>)>) -> &mut Self {
+        self.ranges = Some(Recorder::new(ranges, self.start));
+        self
+    }
+
+    #[inline]
+    pub fn null<K: AsRef<str>>(&mut self, key: K) -> &mut Self {
+        self.field(key.as_ref(), encode_null, ())
+    }
+    #[inline]
+    pub fn bool<K: AsRef<str>>(&mut self, key: K, value: bool) -> &mut Self {
+        self.field(key.as_ref(), encode_bool, value)
+    }
+    #[inline]
+    pub fn number<K: AsRef<str>>(&mut self, key: K, value: f64) -> &mut Self {
+        self.field(key.as_ref(), encode_number, value)
+    }
+    /// Like [`Object::number`], but takes any type which converts to `f64`
+    /// exactly, see [`number_from`].
+    #[inline]
+    pub fn number_from<K: AsRef<str>, N: Into<f64>>(&mut self, key: K, value: N) -> &mut Self {
+        self.field(key.as_ref(), encode_number, value.into())
+    }
+    /// Writes an IEEE 754 half-precision number, given by its bits (as
+    /// returned by `half::f16::to_bits`), with the fewest digits which read
+    /// back as the same `f16`, so `0.1` is `0.1` rather than the
+    /// `0.0999755859375` of widening it to `f64`.
+    #[inline]
+    pub fn number_f16<K: AsRef<str>>(&mut self, key: K, bits: u16) -> &mut Self {
+        self.field(key.as_ref(), encode_narrow, (bits, f16_to_f64))
+    }
+    /// Like [`Object::number_f16`], but for a `bfloat16`.
+    #[inline]
+    pub fn number_bf16<K: AsRef<str>>(&mut self, key: K, bits: u16) -> &mut Self {
+        self.field(key.as_ref(), encode_narrow, (bits, bf16_to_f64))
+    }
+    /// Like [`Object::number`], but only calls `value` if the field is
+    /// written, rather than left out by [`Formatter::skip_fields`] or
+    /// [`Formatter::max_entries`].
+    #[inline]
+    pub fn number_with<K: AsRef<str>, F: FnOnce() -> f64>(
+        &mut self,
+        key: K,
+        value: F,
+    ) -> &mut Self {
+        if self.leaves_out(key.as_ref()) {
+            return self;
+        }
+        self.number(key, value())
+    }
+    /// Writes `value` with exactly `decimals` digits after the point, like
+    /// `9.90` for prices, regardless of the [`Formatter`].
+    #[inline]
+    pub fn number_fixed<K: AsRef<str>>(
+        &mut self,
+        key: K,
+        value: f64,
+        decimals: usize,
+    ) -> &mut Self {
+        self.field(key.as_ref(), encode_fixed, (value, decimals))
+    }
+    /// Writes the fraction `value` as a percentage string with `decimals`
+    /// digits after the point, so `0.1234` with two is `"12.34%"`. Values
+    /// which round to zero have no sign, so `-0.0001` with one is `"0.0%"`.
+    #[inline]
+    pub fn percent<K: AsRef<str>>(&mut self, key: K, value: f64, decimals: usize) -> &mut Self {
+        self.field(key.as_ref(), encode_percent, (value, decimals))
+    }
+    /// Writes `value` rounded to `sig` significant digits, like JavaScript's
+    /// `toPrecision`: `123456` to 3 digits is `1.23e+5`, and `0.00012345` to 2
+    /// is `0.00012`. Unlike there, exact ties round to even, so `2.5` to one
+    /// digit is `2`. A `sig` of zero is taken as one.
+    #[inline]
+    pub fn number_sig<K: AsRef<str>>(&mut self, key: K, value: f64, sig: usize) -> &mut Self {
+        self.field(key.as_ref(), encode_sig, (value, sig))
+    }
+    /// Writes a number given as text with `_` separators, see
+    /// [`number_str_relaxed`]. Writes nothing if it is invalid.
+    #[inline]
+    pub fn number_str_relaxed<K: AsRef<str>>(
+        &mut self,
+        key: K,
+        number: &str,
+    ) -> Result<&mut Self, Error> {
+        let number = strip_separators(number)?;
+        Ok(self.field(key.as_ref(), |buf, _, number| buf.push_str(number), &number))
+    }
+    /// Like [`Object::number`], but writes nothing if `value` is not finite.
+    #[inline]
+    pub fn try_number<K: AsRef<str>>(&mut self, key: K, value: f64) -> Result<&mut Self, Error> {
+        check_finite(value)?;
+        Ok(self.number(key, value))
+    }
+    #[inline]
+    pub fn string<K: AsRef<str>>(&mut self, key: K, value: &str) -> &mut Self {
+        self.field(key.as_ref(), encode_str, value)
+    }
+    /// Writes `value`, which is already escaped, in quotes as is, for strings
+    /// escaped ahead of time, like with [`Formatter::escape_into_slice`].
+    ///
+    /// Panics in debug builds if `value` has a `"`, a control character or
+    /// an invalid escape sequence.
+    #[inline]
+    pub fn string_preescaped<K: AsRef<str>>(&mut self, key: K, value: &str) -> &mut Self {
+        self.field(key.as_ref(), encode_preescaped, value)
+    }
+    /// Writes the `"$schema"` field of a JSON Schema document, which by
+    /// convention comes first.
+    #[inline]
+    pub fn schema(&mut self, url: &str) -> &mut Self {
+        self.string("$schema", url)
+    }
+    /// Like [`Object::string`], but takes the characters from an iterator.
+    #[inline]
+    pub fn string_chars<K: AsRef<str>, I: IntoIterator<Item = char>>(
+        &mut self,
+        key: K,
+        chars: I,
+    ) -> &mut Self {
+        self.field(key.as_ref(), encode_chars, chars)
+    }
+    /// Like [`Object::string`], but takes bytes and writes nothing if they are
+    /// not valid UTF-8.
+    #[inline]
+    pub fn try_string_bytes<K: AsRef<str>>(
+        &mut self,
+        key: K,
+        value: &[u8],
+    ) -> Result<&mut Self, Error> {
+        Ok(self.string(key, std::str::from_utf8(value)?))
+    }
+    /// Like [`Object::try_string_bytes`], but skips the validation.
+    ///
+    /// # Safety
+    ///
+    /// `value` must be valid UTF-8, see [`string_bytes_unchecked`].
+    #[inline]
+    pub unsafe fn string_bytes_unchecked<K: AsRef<str>>(
+        &mut self,
+        key: K,
+        value: &[u8],
+    ) -> &mut Self {
+        self.string(key, std::str::from_utf8_unchecked(value))
+    }
+    /// Writes `key`, and then lets `enc` write the value directly to the
+    /// buffer.
+    ///
+    /// `enc` must write exactly one valid JSON value.
+    #[inline]
+    pub fn custom<K, T, F>(&mut self, key: K, value: T, enc: F) -> &mut Self
+    where
+        K: AsRef<str>,
+        F: FnOnce(&mut String, T),
+    {
+        self.field(key.as_ref(), |buf, _, value| enc(buf, value), value)
+    }
+    /// Writes `scratch`, which must be one valid JSON value, as is, for
+    /// values serialized separately into a scratch buffer. This also passes
+    /// through JSON which was never parsed, like a `serde_json` `RawValue`:
+    /// `obj.field_from_scratch("payload", raw.get())`.
+    ///
+    /// Panics in debug builds if `scratch` is blank or its brackets don't
+    /// balance.
+    #[inline]
+    pub fn field_from_scratch<K: AsRef<str>>(&mut self, key: K, scratch: &str) -> &mut Self {
+        check_scratch(scratch);
+        self.field(
+            key.as_ref(),
+            |buf, _, scratch| buf.push_str(scratch),
+            scratch,
+        )
+    }
+    /// Like [`Object::custom`], but with a key escaped ahead of time, which
+    /// saves escaping it over and over in loops.
+    ///
+    /// The key is written as prepared: this object's key prefix and
+    /// [`Formatter`] don't apply to it.
+    #[inline]
+    pub fn field_prepared<T, F>(&mut self, key: &PreparedKey, value: T, enc: F) -> &mut Self
+    where
+        F: FnOnce(&mut String, T),
+    {
+        self.count();
+        self.separator();
+        self.buf.push_str(&key.text);
+        self.record(&key.key);
+        enc(self.buf, value);
+        self
+    }
+    /// Writes the same value under each of `keys`, encoding it with `enc`
+    /// only once.
+    ///
+    /// `enc` must write exactly one valid JSON value.
+    #[inline]
+    pub fn aliases<K, T, F>(&mut self, keys: &[K], value: T, enc: F) -> &mut Self
+    where
+        K: AsRef<str>,
+        F: FnOnce(&mut String, T),
+    {
+        match keys {
+            [] => self,
+            [key] => self.custom(key, value, enc),
+            keys => {
+                let mut encoded = String::new();
+                enc(&mut encoded, value);
+                for key in keys {
+                    self.field(key.as_ref(), |buf, _, value| buf.push_str(value), &encoded);
+                }
+                self
+            }
+        }
+    }
+    /// Writes the first `Some` of `options` with `enc`, like
+    /// [`Object::custom`]. If all are `None`, writes `null` if `or_null` is
+    /// set, and omits the field otherwise.
+    #[inline]
+    pub fn first_some<K, T, F>(
+        &mut self,
+        key: K,
+        options: &[Option<T>],
+        or_null: bool,
+        enc: F,
+    ) -> &mut Self
+    where
+        K: AsRef<str>,
+        F: FnOnce(&mut String, &T),
+    {
+        match options.iter().flatten().next() {
+            Some(value) => self.custom(key, value, enc),
+            None if or_null => self.null(key),
+            None => {
+                self.omit(key.as_ref());
+                self
+            }
+        }
+    }
+    /// Writes `bytes` as an array of numbers, like `[222,173]`.
+    #[inline]
+    pub fn bytes_as_numbers<K: AsRef<str>>(&mut self, key: K, bytes: &[u8]) -> &mut Self {
+        self.field(key.as_ref(), encode_bytes_as_numbers, bytes)
+    }
+    /// Writes a `width`-character number field to be filled in later with
+    /// [`Placeholder::fill`].
+    ///
+    /// If the field is left out by [`Formatter::skip_fields`] or the
+    /// limits, nothing is written, and filling the placeholder does nothing.
+    #[inline]
+    pub fn placeholder<K: AsRef<str>>(&mut self, key: K, width: usize) -> Placeholder {
+        if self.leaves_out(key.as_ref()) {
+            return Placeholder::inert(width);
+        }
+        self.key(key.as_ref());
+        Placeholder::new(self.buf, width)
+    }
+    /// Writes `duration` as an ISO 8601 duration string, like `"PT1M30.5S"`.
+    ///
+    /// Only hours, minutes and seconds are used, as days and longer units
+    /// vary in length. Seconds keep their fractional part, down to
+    /// nanoseconds, and a zero duration is `"PT0S"`.
+    #[inline]
+    pub fn duration_iso8601<K: AsRef<str>>(&mut self, key: K, duration: Duration) -> &mut Self {
+        self.field(key.as_ref(), encode_duration_iso8601, duration)
+    }
+    /// Writes a GeoJSON position, `[longitude, latitude]`, with `decimals`
+    /// digits after the point, see [`Object::number_fixed`]. Seven decimals
+    /// are about a centimeter.
+    #[inline]
+    pub fn position<K: AsRef<str>>(
+        &mut self,
+        key: K,
+        position: [f64; 2],
+        decimals: usize,
+    ) -> &mut Self {
+        self.field(key.as_ref(), encode_position, (position, decimals))
+    }
+    /// Writes an array of GeoJSON positions, like a line string or a ring,
+    /// see [`Object::position`].
+    #[inline]
+    pub fn coordinates<K: AsRef<str>>(
+        &mut self,
+        key: K,
+        positions: &[[f64; 2]],
+        decimals: usize,
+    ) -> &mut Self {
+        self.field(key.as_ref(), encode_coordinates, (positions, decimals))
+    }
+    /// Writes a calendar date as an ISO 8601 string, like `"2024-01-15"`.
+    ///
+    /// The year is written with four digits, so year 987 is `"0987"`.
+    ///
+    /// # Panics
+    ///
+    /// In debug builds, if the year is not in `0..=9999` or the month has
+    /// no such day, taking leap years into account.
+    #[inline]
+    pub fn date<K: AsRef<str>>(&mut self, key: K, year: i32, month: u8, day: u8) -> &mut Self {
+        self.field(key.as_ref(), encode_date, (year, month, day))
+    }
+    /// Writes the CIDR block `addr/prefix` as a string, like `"10.0.0.0/8"`.
+    ///
+    /// # Panics
+    ///
+    /// If `prefix` is longer than the address.
+    #[inline]
+    pub fn cidr<K: AsRef<str>, A: Into<IpAddr>>(
+        &mut self,
+        key: K,
+        addr: A,
+        prefix: u8,
+    ) -> &mut Self {
+        self.field(key.as_ref(), encode_cidr, (addr.into(), prefix))
+    }
+    /// Like [`Object::cidr`], but writes an object, like
+    /// `{"addr":"10.0.0.0","prefix":8}`.
+    #[inline]
+    pub fn cidr_object<K: AsRef<str>, A: Into<IpAddr>>(
+        &mut self,
+        key: K,
+        addr: A,
+        prefix: u8,
+    ) -> &mut Self {
+        self.field(key.as_ref(), encode_cidr_object, (addr.into(), prefix))
+    }
+    /// Writes `bits` packed into a hex string, as `{"len":10,"hex":"b1c0"}`.
+    ///
+    /// Bits fill each byte from the most significant one, and the last byte
+    /// is padded with zeros. This is a compact encoding of its own rather
+    /// than a JSON array, so the reader must unpack it.
+    #[inline]
+    pub fn bitset<K: AsRef<str>>(&mut self, key: K, bits: &[bool]) -> &mut Self {
+        self.field(key.as_ref(), encode_bitset, bits)
+    }
+    /// Writes `numbers` run-length encoded, as `[[value,count],...]`, so
+    /// `[0,0,0,5,5]` becomes `[[0,3],[5,2]]`.
+    ///
+    /// The result is valid JSON, but not an array of `numbers`: the reader
+    /// must expand the runs itself.
+    #[inline]
+    pub fn run_length<K: AsRef<str>>(&mut self, key: K, numbers: &[f64]) -> &mut Self {
+        self.field(key.as_ref(), encode_run_length, numbers)
+    }
+    /// Writes `value` in base `radix` as a string, after `prefix`, so `255`
+    /// in base 16 with `"0x"` is `"0xff"`. Digits above 9 are lowercase
+    /// letters, and a negative value gets its `-` before the prefix.
+    ///
+    /// # Panics
+    ///
+    /// If `radix` is not in `2..=36`.
+    #[inline]
+    pub fn int_radix<K: AsRef<str>>(
+        &mut self,
+        key: K,
+        value: i64,
+        radix: u32,
+        prefix: &str,
+    ) -> &mut Self {
+        self.field(key.as_ref(), encode_int_radix, (value, radix, prefix))
+    }
+    /// Writes `range` as a two-element `[start, end]` array. Like the
+    /// `Range` itself, `end` is exclusive.
+    #[inline]
+    pub fn range<K: AsRef<str>>(&mut self, key: K, range: Range<i64>) -> &mut Self {
+        self.field(key.as_ref(), encode_range, range)
+    }
+    /// Writes an array field with an element for every item of `iter`,
+    /// encoded with `enc`, and returns `self` to continue the chain.
+    #[inline]
+    pub fn array_of<K: AsRef<str>, T, I, F>(&mut self, key: K, iter: I, mut enc: F) -> &mut Self
+    where
+        I: IntoIterator<Item = T>,
+        F: FnMut(&mut String, T),
+    {
+        let mut arr = self.array(key);
+        for item in iter {
+            arr.custom(item, &mut enc);
+        }
+        drop(arr);
+        self
+    }
+    /// Writes an object field with a field for every `(key, value)` pair of
+    /// `iter`, with values encoded by `enc`, and returns `self` to continue the
+    /// chain.
+    #[inline]
+    pub fn object_of<K, L, T, I, F>(&mut self, key: K, iter: I, mut enc: F) -> &mut Self
+    where
+        K: AsRef<str>,
+        L: AsRef<str>,
+        I: IntoIterator<Item = (L, T)>,
+        F: FnMut(&mut String, T),
+    {
+        let mut obj = self.object(key);
+        for (key, value) in iter {
+            obj.custom(key, value, &mut enc);
+        }
+        drop(obj);
+        self
+    }
+    /// Writes `pairs` as an object field if their keys are unique, and as an
+    /// array of `[key, value]` arrays otherwise, so that no pair is lost
+    /// to a reader which keeps only one value per key. See [`pairs`].
+    #[inline]
+    pub fn pairs<K, L, T, F>(&mut self, key: K, pairs: &[(L, T)], mut enc: F) -> &mut Self
+    where
+        K: AsRef<str>,
+        L: AsRef<str>,
+        F: FnMut(&mut String, &T),
+    {
+        if keys_unique(pairs) {
+            let iter = pairs.iter().map(|(key, value)| (key, value));
+            return self.object_of(key, iter, enc);
+        }
+        let mut arr = self.array(key);
+        for (key, value) in pairs {
+            arr.array().string(key.as_ref()).custom(value, &mut enc);
+        }
+        drop(arr);
+        self
+    }
+    /// Writes a field for every `(key, value)` pair of `iter`, using the
+    /// `Display` impl of keys (an enum's variant names, say) and encoding
+    /// values with `enc`.
+    #[inline]
+    pub fn entries<K, T, I, F>(&mut self, iter: I, mut enc: F) -> &mut Self
+    where
+        K: fmt::Display,
+        I: IntoIterator<Item = (K, T)>,
+        F: FnMut(&mut String, T),
+    {
+        for (key, value) in iter {
+            self.key_with(|buf, fmt| {
+                use std::fmt::Write;
+                let _ = write!(Escaper { buf, fmt }, "{key}");
+            });
+            if self.ranges.is_some() {
+                self.record(&key.to_string());
+            }
+            enc(self.buf, value);
+        }
+        self
+    }
+    /// Starts a nested object under `key`.
+    ///
+    /// The returned `Object` mutably borrows `self` until it is dropped, at
+    /// which point the closing `}` is written and `self` can be used again.
+    /// Either bind it (`let mut inner = obj.object("k");`) and let it go out
+    /// of scope, or chain on it to the end of a statement.
+    #[inline]
+    pub fn object<K: AsRef<str>>(&mut self, key: K) -> Object<'_> {
+        self.key(key.as_ref());
+        let mut obj = Object::new(self.buf, self.fmt, self.depth + 1);
+        obj.ranges = self.ranges.as_mut().map(Recorder::child);
+        obj
+    }
+    /// Starts a nested array under `key`.
+    ///
+    /// See [`Object::object`] for how the borrow works.
+    #[inline]
+    pub fn array<K: AsRef<str>>(&mut self, key: K) -> Array<'_> {
+        self.key(key.as_ref());
+        let mut arr = Array::new(self.buf, self.fmt, self.depth + 1);
+        arr.ranges = self.ranges.as_mut().map(Recorder::child);
+        arr
+    }
+    /// Starts a nested object under `key` which hands `self` back from
+    /// [`NestedObject::done`], so that the chain can go on with the next
+    /// field of `self`.
+    #[inline]
+    pub fn nested<K: AsRef<str>>(&mut self, key: K) -> NestedObject<&mut Object<'a>> {
+        NestedObject::new(self, key.as_ref())
+    }
+    /// Starts a nested object under `key` if `cond` holds, and writes
+    /// nothing otherwise.
+    #[inline]
+    pub fn object_if<K: AsRef<str>>(&mut self, key: K, cond: bool) -> Option<Object<'_>> {
+        if cond {
+            Some(self.object(key))
+        } else {
+            self.omit(key.as_ref());
+            None
+        }
+    }
+    /// Writes a nested object under `key`, filled in by `f`, and returns
+    /// `self`, so that nested documents read top to bottom in one chain:
+    ///
+    /// ```
+    /// let mut buf = String::new();
+    /// write_json::object(&mut buf)
+    ///     .object_with("user", |user| {
+    ///         user.string("name", "Peter");
+    ///     })
+    ///     .number("version", 1.0);
+    /// assert_eq!(buf, r#"{"user":{"name":"Peter"},"version":1}"#);
+    /// ```
+    #[inline]
+    pub fn object_with<K: AsRef<str>, F: FnOnce(&mut Object<'_>)>(
+        &mut self,
+        key: K,
+        f: F,
+    ) -> &mut Self {
+        f(&mut self.object(key));
+        self
+    }
+    /// Like [`Object::object_with`], but for a nested array.
+    #[inline]
+    pub fn array_with<K: AsRef<str>, F: FnOnce(&mut Array<'_>)>(
+        &mut self,
+        key: K,
+        f: F,
+    ) -> &mut Self {
+        f(&mut self.array(key));
+        self
+    }
+    /// Alias for [`Object::null`].
+    #[inline]
+    pub fn put_null<K: AsRef<str>>(&mut self, key: K) -> &mut Self {
+        self.null(key)
+    }
+    /// Alias for [`Object::bool`].
+    #[inline]
+    pub fn put_bool<K: AsRef<str>>(&mut self, key: K, value: bool) -> &mut Self {
+        self.bool(key, value)
+    }
+    /// Alias for [`Object::number`].
+    #[inline]
+    pub fn put_number<K: AsRef<str>>(&mut self, key: K, value: f64) -> &mut Self {
+        self.number(key, value)
+    }
+    /// Alias for [`Object::string`].
+    #[inline]
+    pub fn put_string<K: AsRef<str>>(&mut self, key: K, value: &str) -> &mut Self {
+        self.string(key, value)
+    }
+    /// Alias for [`Object::object`].
+    #[inline]
+    pub fn put_object<K: AsRef<str>>(&mut self, key: K) -> Object<'_> {
+        self.object(key)
+    }
+    /// Alias for [`Object::array`].
+    #[inline]
+    pub fn put_array<K: AsRef<str>>(&mut self, key: K) -> Array<'_> {
+        self.array(key)
+    }
+}
+
+impl Drop for Object<'_> {
+    #[inline]
+    fn drop(&mut self) {
+        self.close()
+    }
+}
+
+pub struct Array<'a> {
+    buf: &'a mut String,
+    fmt: Formatter,
+    depth: usize,
+    len: usize,
+    /// Where the elements past [`Formatter::max_entries`] start.
+    cut: Option<usize>,
+    /// Where the opening `[` is, if known.
+    start: Option<usize>,
+    /// Where the last element starts, for [`Formatter::max_bytes`].
+    last: usize,
+    /// The length which exceeded [`Formatter::max_bytes`], and the number of
+    /// elements kept.
+    exceeded: Option<(usize, usize)>,
+    ranges: Option<Recorder<'a>>,
+}
+
+impl<'a> Array<'a> {
+    #[inline]
+    fn new(buf: &'a mut String, fmt: Formatter, depth: usize) -> Self {
+        let start = Some(buf.len());
+        buf.push('[');
+        Array {
+            buf,
+            fmt,
+            depth,
+            len: 0,
+            cut: None,
+            start,
+            last: 0,
+            exceeded: None,
+            ranges: None,
+        }
+    }
+    #[inline]
+    fn comma(&mut self) {
+        if let Some(ranges) = &mut self.ranges {
+            ranges.end(self.buf.len());
+        }
+        self.check_bytes();
+        match self.cut {
+            Some(cut) => self.truncate(cut),
+            None if self.len == self.fmt.max_entries => self.cut = Some(self.buf.len()),
+            None => (),
+        }
+        self.last = self.buf.len();
+        if self.len > 0 {
+            self.buf.push(',');
+        }
+        if self.len > 0 && self.ends_chunk(self.len) {
+            self.index_comment(self.len - 1);
+            self.fmt.newline(self.buf, self.depth + 1);
+        } else if self.len.is_multiple_of(self.fmt.per_line) {
+            self.fmt.newline(self.buf, self.depth + 1);
+        } else if self.fmt.pretty {
+            self.buf.push(' ');
+        }
+        self.len += 1;
+        if let Some(ranges) = &mut self.ranges {
+            ranges.open(self.buf.len(), &(self.len - 1).to_string());
+        }
+    }
+    /// Like [`Object::truncate`].
+    #[inline]
+    fn truncate(&mut self, len: usize) {
+        self.buf.truncate(len);
+        if let Some(ranges) = &mut self.ranges {
+            ranges.forget_from(len);
+        }
+    }
+    /// Like [`Object::check_bytes`].
+    #[inline]
+    fn check_bytes(&mut self) {
+        let Some(start) = self.start else { return };
+        let len = self.buf.len() - start;
+        if self.cut.is_none() && self.len > 0 && len > self.fmt.max_bytes {
+            self.cut = Some(self.last);
+            self.exceeded = Some((len, self.len - 1));
+        }
+    }
+    /// Whether an element past the limits is left out, in which case it is
+    /// accounted for as if it was written and removed.
+    #[inline]
+    fn leaves_out(&mut self) -> bool {
+        self.check_bytes();
+        if self.cut.is_some() || self.len >= self.fmt.max_entries {
+            if self.cut.is_none() {
+                self.cut = Some(self.buf.len());
+            }
+            self.len += 1;
+            return true;
+        }
+        false
+    }
+    /// Whether an index comment goes after the first `len` elements.
+    #[inline]
+    fn ends_chunk(&self, len: usize) -> bool {
+        self.fmt.pretty && self.fmt.comment_every > 0 && len.is_multiple_of(self.fmt.comment_every)
+    }
+    /// Writes the index comment for the chunk ending at `last`.
+    #[inline(never)]
+    fn index_comment(&mut self, last: usize) {
+        use std::fmt::Write;
+        let first = last - last % self.fmt.comment_every;
+        self.fmt.newline(self.buf, self.depth + 1);
+        let _ = write!(self.buf, "// items {first}-{last}");
+    }
+    #[inline]
+    fn element<T, F>(&mut self, enc: F, value: T) -> &mut Self
+    where
+        F: FnOnce(&mut String, &Formatter, T),
+    {
+        self.comma();
+        enc(self.buf, &self.fmt, value);
+        self
+    }
+
+    #[inline]
+    pub fn null(&mut self) -> &mut Self {
+        self.element(encode_null, ())
+    }
+    #[inline]
+    pub fn bool(&mut self, value: bool) -> &mut Self {
+        self.element(encode_bool, value)
+    }
+    #[inline]
+    pub fn number(&mut self, value: f64) -> &mut Self {
+        self.element(encode_number, value)
+    }
+    /// Like [`Object::number_from`].
+    #[inline]
+    pub fn number_from<N: Into<f64>>(&mut self, value: N) -> &mut Self {
+        self.element(encode_number, value.into())
+    }
+    /// Like [`Object::number_f16`].
+    #[inline]
+    pub fn number_f16(&mut self, bits: u16) -> &mut Self {
+        self.element(encode_narrow, (bits, f16_to_f64))
+    }
+    /// Like [`Object::number_bf16`].
+    #[inline]
+    pub fn number_bf16(&mut self, bits: u16) -> &mut Self {
+        self.element(encode_narrow, (bits, bf16_to_f64))
+    }
+    /// Like [`Object::number_fixed`].
+    #[inline]
+    pub fn number_fixed(&mut self, value: f64, decimals: usize) -> &mut Self {
+        self.element(encode_fixed, (value, decimals))
+    }
+    /// Like [`Object::percent`].
+    #[inline]
+    pub fn percent(&mut self, value: f64, decimals: usize) -> &mut Self {
+        self.element(encode_percent, (value, decimals))
+    }
+    /// Like [`Object::number_sig`].
+    #[inline]
+    pub fn number_sig(&mut self, value: f64, sig: usize) -> &mut Self {
+        self.element(encode_sig, (value, sig))
+    }
+    /// Like [`Object::number_str_relaxed`].
+    #[inline]
+    pub fn number_str_relaxed(&mut self, number: &str) -> Result<&mut Self, Error> {
+        let number = strip_separators(number)?;
+        Ok(self.element(|buf, _, number| buf.push_str(number), &number))
+    }
+    /// Like [`Array::number`], but writes nothing if `value` is not finite.
+    #[inline]
+    pub fn try_number(&mut self, value: f64) -> Result<&mut Self, Error> {
+        check_finite(value)?;
+        Ok(self.number(value))
+    }
+    #[inline]
+    pub fn string(&mut self, value: &str) -> &mut Self {
+        self.element(encode_str, value)
+    }
+    /// Like [`Object::string_preescaped`].
+    #[inline]
+    pub fn string_preescaped(&mut self, value: &str) -> &mut Self {
+        self.element(encode_preescaped, value)
+    }
+    /// Like [`Array::string`], but takes the characters from an iterator.
+    #[inline]
+    pub fn string_chars<I: IntoIterator<Item = char>>(&mut self, chars: I) -> &mut Self {
+        self.element(encode_chars, chars)
+    }
+    /// Like [`Array::string`], but takes bytes and writes nothing if they are
+    /// not valid UTF-8.
+    #[inline]
+    pub fn try_string_bytes(&mut self, value: &[u8]) -> Result<&mut Self, Error> {
+        Ok(self.string(std::str::from_utf8(value)?))
+    }
+    /// Like [`Array::try_string_bytes`], but skips the validation.
+    ///
+    /// # Safety
+    ///
+    /// `value` must be valid UTF-8, see [`string_bytes_unchecked`].
+    #[inline]
+    pub unsafe fn string_bytes_unchecked(&mut self, value: &[u8]) -> &mut Self {
+        self.string(std::str::from_utf8_unchecked(value))
+    }
+    /// Writes every item of `iter`, encoding `Some` values with `enc` and
+    /// `None` as `null`.
+    #[inline]
+    pub fn options<T, I, F>(&mut self, iter: I, mut enc: F) -> &mut Self
+    where
+        I: IntoIterator<Item = Option<T>>,
+        F: FnMut(&mut String, T),
+    {
+        for item in iter {
+            match item {
+                Some(value) => self.element(|buf, _, value| enc(buf, value), value),
+                None => self.null(),
+            };
+        }
+        self
+    }
+    /// Writes `bytes` as an array of numbers, like `[222,173]`.
+    #[inline]
+    pub fn bytes_as_numbers(&mut self, bytes: &[u8]) -> &mut Self {
+        self.element(encode_bytes_as_numbers, bytes)
+    }
+    /// Writes a `width`-character number element to be filled in later with
+    /// [`Placeholder::fill`].
+    ///
+    /// Past [`Formatter::max_entries`] or [`Formatter::max_bytes`], nothing
+    /// is written, and filling the placeholder does nothing.
+    #[inline]
+    pub fn placeholder(&mut self, width: usize) -> Placeholder {
+        if self.leaves_out() {
+            return Placeholder::inert(width);
+        }
+        self.comma();
+        Placeholder::new(self.buf, width)
+    }
+    /// Like [`Object::duration_iso8601`].
+    #[inline]
+    pub fn duration_iso8601(&mut self, duration: Duration) -> &mut Self {
+        self.element(encode_duration_iso8601, duration)
+    }
+    /// Like [`Object::position`].
+    #[inline]
+    pub fn position(&mut self, position: [f64; 2], decimals: usize) -> &mut Self {
+        self.element(encode_position, (position, decimals))
+    }
+    /// Like [`Object::coordinates`], for example for the rings of a polygon.
+    #[inline]
+    pub fn coordinates(&mut self, positions: &[[f64; 2]], decimals: usize) -> &mut Self {
+        self.element(encode_coordinates, (positions, decimals))
+    }
+    /// Like [`Object::date`].
+    #[inline]
+    pub fn date(&mut self, year: i32, month: u8, day: u8) -> &mut Self {
+        self.element(encode_date, (year, month, day))
+    }
+    /// Writes the code point of every `char` in `range` as a number
+    /// element, so `'a'..='c'` gives `97,98,99`. Writes nothing and fails
+    /// with [`Error::TooLarge`] if there are more than `max_len` of them.
+    #[inline]
+    pub fn char_range(
+        &mut self,
+        range: RangeInclusive<char>,
+        max_len: usize,
+    ) -> Result<&mut Self, Error> {
+        // Surrogates are not `char`s, so the range skips them.
+        let (start, end) = (u32::from(*range.start()), u32::from(*range.end()));
+        let mut len = (end + 1).saturating_sub(start) as usize;
+        if start < 0xD800 && end > 0xDFFF {
+            len -= 0x800;
+        }
+        if len > max_len {
+            return Err(Error::TooLarge(len));
+        }
+        for c in range {
+            self.element(|buf, _, c| encode_int(buf, c.into()), u32::from(c));
+        }
+        Ok(self)
+    }
+    /// Like [`Object::cidr`].
+    #[inline]
+    pub fn cidr<A: Into<IpAddr>>(&mut self, addr: A, prefix: u8) -> &mut Self {
+        self.element(encode_cidr, (addr.into(), prefix))
+    }
+    /// Like [`Object::cidr_object`].
+    #[inline]
+    pub fn cidr_object<A: Into<IpAddr>>(&mut self, addr: A, prefix: u8) -> &mut Self {
+        self.element(encode_cidr_object, (addr.into(), prefix))
+    }
+    /// Like [`Object::bitset`].
+    #[inline]
+    pub fn bitset(&mut self, bits: &[bool]) -> &mut Self {
+        self.element(encode_bitset, bits)
+    }
+    /// Like [`Object::run_length`].
+    #[inline]
+    pub fn run_length(&mut self, numbers: &[f64]) -> &mut Self {
+        self.element(encode_run_length, numbers)
+    }
+    /// Like [`Object::int_radix`].
+    #[inline]
+    pub fn int_radix(&mut self, value: i64, radix: u32, prefix: &str) -> &mut Self {
+        self.element(encode_int_radix, (value, radix, prefix))
+    }
+    /// Writes `range` as a two-element `[start, end]` array. Like the
+    /// `Range` itself, `end` is exclusive.
+    #[inline]
+    pub fn range(&mut self, range: Range<i64>) -> &mut Self {
+        self.element(encode_range, range)
+    }
+    /// Lets `enc` write an element directly to the buffer.
+    ///
+    /// `enc` must write exactly one valid JSON value.
+    #[inline]
+    pub fn custom<T, F: FnOnce(&mut String, T)>(&mut self, value: T, enc: F) -> &mut Self {
+        self.element(|buf, _, value| enc(buf, value), value)
+    }
+    /// Appends already serialized elements, such as the ones returned by
+    /// [`array_elements`]. Nothing is written if `elements` is blank.
+    ///
+    /// `elements` must be comma-separated valid JSON values. They count as a
+    /// single element for [`Formatter::elements_per_line`].
+    #[inline]
+    pub fn extend_raw(&mut self, elements: &str) -> &mut Self {
+        let elements = elements.trim();
+        if elements.is_empty() {
+            return self;
+        }
+        self.element(|buf, _, elements| buf.push_str(elements), elements)
     }
+    /// Like [`Object::field_from_scratch`]. Unlike [`Array::extend_raw`],
+    /// `scratch` is a single element.
     #[inline]
-    fn key(&mut self, key: &str) {
-        if !self.first {
-            self.buf.push(',');
+    pub fn element_from_scratch(&mut self, scratch: &str) -> &mut Self {
+        check_scratch(scratch);
+        self.element(|buf, _, scratch| buf.push_str(scratch), scratch)
+    }
+    /// Starts a nested object element.
+    ///
+    /// See [`Object::object`] for how the borrow works.
+    #[inline]
+    pub fn object(&mut self) -> Object<'_> {
+        self.comma();
+        let mut obj = Object::new(self.buf, self.fmt, self.depth + 1);
+        obj.ranges = self.ranges.as_mut().map(Recorder::child);
+        obj
+    }
+    /// Starts a nested array element.
+    ///
+    /// See [`Object::object`] for how the borrow works.
+    #[inline]
+    pub fn array(&mut self) -> Array<'_> {
+        self.comma();
+        let mut arr = Array::new(self.buf, self.fmt, self.depth + 1);
+        arr.ranges = self.ranges.as_mut().map(Recorder::child);
+        arr
+    }
+    /// Like [`Object::object_with`].
+    #[inline]
+    pub fn object_with<F: FnOnce(&mut Object<'_>)>(&mut self, f: F) -> &mut Self {
+        f(&mut self.object());
+        self
+    }
+    /// Like [`Object::array_with`].
+    #[inline]
+    pub fn array_with<F: FnOnce(&mut Array<'_>)>(&mut self, f: F) -> &mut Self {
+        f(&mut self.array());
+        self
+    }
+    /// Writes an object for every item of `rows`, all with the same `keys`,
+    /// which are escaped once up front rather than for every row.
+    ///
+    /// `f` writes the value of field `i` of a row, and must write exactly
+    /// one valid JSON value.
+    #[inline]
+    pub fn objects_with_keys<T, I, F>(
+        &mut self,
+        keys: &[PreparedKey],
+        rows: I,
+        mut f: F,
+    ) -> &mut Self
+    where
+        I: IntoIterator<Item = T>,
+        F: FnMut(&mut String, &T, usize),
+    {
+        for row in rows {
+            let mut obj = self.object();
+            for (i, key) in keys.iter().enumerate() {
+                obj.field_prepared(key, &row, |buf, row| f(buf, row, i));
+            }
         }
-        self.first = false;
-        encode_str(&mut self.buf, key);
-        self.buf.push(':');
+        self
     }
+    /// Like [`Object::record_ranges`].
     #[inline]
-    fn field<T, F: FnOnce(&mut String, T)>(&mut self, key: &str, enc: F, value: T) -> &mut Self {
-        self.key(key);
-        enc(&mut self.buf, value);
+    pub fn record_ranges(&mut self, ranges: &'a mut Vec<(String, Range<usize>)>) -> &mut Self {
+        if let Some(start) = self.start {
+            self.ranges = Some(Recorder::new(ranges, start));
+        }
         self
     }
+    /// Like [`Object::finish`].
+    #[inline]
+    pub fn finish(mut self) -> Result<(), Error> {
+        self.close();
+        let exceeded = self.exceeded;
+        std::mem::forget(self);
+        exceeded.map_or(Ok(()), |(len, _)| Err(Error::SizeLimitExceeded(len)))
+    }
+    /// Stops writing to this array without closing it, so that it can be
+    /// continued later with [`resume_array`].
+    ///
+    /// Only the buffer is needed to resume: the array is empty so far iff
+    /// the buffer ends with `[`. Nothing else may be written to the buffer
+    /// in between.
+    ///
+    /// Elements past [`Formatter::max_entries`] or [`Formatter::max_bytes`]
+    /// are removed here, but the resumed array counts from zero again, and
+    /// starts at depth zero, so [`Formatter::pretty`] output and the
+    /// [`Formatter::elements_per_line`] and [`Formatter::index_comments`]
+    /// layouts do not carry over a suspend.
+    #[inline]
+    pub fn suspend(mut self) {
+        self.check_bytes();
+        if let Some(cut) = self.cut {
+            self.truncate(cut);
+        }
+        std::mem::forget(self)
+    }
 
+    /// Alias for [`Array::null`].
     #[inline]
-    pub fn null(&mut self, key: &str) -> &mut Self {
-        self.field(key, encode_null, ())
+    pub fn put_null(&mut self) -> &mut Self {
+        self.null()
     }
+    /// Alias for [`Array::bool`].
     #[inline]
-    pub fn bool(&mut self, key: &str, value: bool) -> &mut Self {
-        self.field(key, encode_bool, value)
+    pub fn put_bool(&mut self, value: bool) -> &mut Self {
+        self.bool(value)
     }
+    /// Alias for [`Array::number`].
     #[inline]
-    pub fn number(&mut self, key: &str, value: f64) -> &mut Self {
-        self.field(key, encode_number, value)
+    pub fn put_number(&mut self, value: f64) -> &mut Self {
+        self.number(value)
     }
+    /// Alias for [`Array::string`].
     #[inline]
-    pub fn string(&mut self, key: &str, value: &str) -> &mut Self {
-        self.field(key, encode_str, value)
+    pub fn put_string(&mut self, value: &str) -> &mut Self {
+        self.string(value)
     }
+    /// Alias for [`Array::object`].
     #[inline]
-    pub fn object(&mut self, key: &str) -> Object<'_> {
-        self.key(key);
-        Object::new(self.buf)
+    pub fn put_object(&mut self) -> Object<'_> {
+        self.object()
     }
+    /// Alias for [`Array::array`].
     #[inline]
-    pub fn array(&mut self, key: &str) -> Array<'_> {
-        self.key(key);
-        Array::new(self.buf)
+    pub fn put_array(&mut self) -> Array<'_> {
+        self.array()
     }
 }
 
-impl Drop for Object<'_> {
+impl Drop for Array<'_> {
     #[inline]
     fn drop(&mut self) {
-        self.buf.push('}')
+        self.close()
     }
 }
 
-pub struct Array<'a> {
-    buf: &'a mut String,
-    first: bool,
+impl Array<'_> {
+    #[inline]
+    fn close(&mut self) {
+        if let Some(ranges) = &mut self.ranges {
+            ranges.end(self.buf.len());
+        }
+        self.check_bytes();
+        if let Some(cut) = self.cut {
+            self.truncate(cut);
+        }
+        let len = match self.exceeded {
+            Some((_, kept)) => kept,
+            None => self.len,
+        };
+        let len = len.min(self.fmt.max_entries);
+        if len > 0 && self.fmt.pretty && self.fmt.comment_every > 0 {
+            self.index_comment(len - 1);
+        }
+        if len > 0 {
+            self.fmt.newline(self.buf, self.depth);
+        }
+        if let Some(start) = self.start {
+            debug_assert!(
+                !self.fmt.check_balance || text::unclosed(&self.buf[start..]) == 1,
+                "a container nested in this array was not closed"
+            );
+        }
+        self.buf.push(']');
+        if let Some(ranges) = &mut self.ranges {
+            ranges.close(self.buf.len());
+        }
+    }
 }
 
-impl<'a> Array<'a> {
+/// An object key which is already escaped and quoted, followed by the `:`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PreparedKey {
+    text: String,
+    /// The key as written, for [`Object::record_ranges`].
+    key: Box<str>,
+}
+
+impl PreparedKey {
+    /// Escapes `key` with the default [`Formatter`].
     #[inline]
-    fn new(buf: &'a mut String) -> Self {
-        buf.push('[');
-        Array { buf, first: true }
+    pub fn new(key: &str) -> PreparedKey {
+        Formatter::new().prepare_key(key)
     }
+}
+
+/// A map from keys to their [`PreparedKey`], for keys which are only known
+/// at runtime but recur, like the field names of a tree read from a schema.
+///
+/// Nothing consults it implicitly: look keys up with [`PreparedKeys::get`]
+/// and pass them to [`Object::field_prepared`]. The map borrows the keys
+/// rather than copying them.
+#[derive(Debug, Default)]
+pub struct PreparedKeys<'k> {
+    fmt: Formatter,
+    keys: std::collections::HashMap<&'k str, PreparedKey>,
+}
+
+impl<'k> PreparedKeys<'k> {
+    /// Creates an empty map which escapes keys with `fmt`, which should be
+    /// the formatter of the objects the keys are written to.
     #[inline]
-    fn comma(&mut self) {
-        if !self.first {
-            self.buf.push(',');
+    pub fn new(fmt: Formatter) -> PreparedKeys<'k> {
+        PreparedKeys {
+            fmt,
+            keys: Default::default(),
         }
-        self.first = false;
     }
+    /// Returns `key` prepared, escaping it only the first time it is asked
+    /// for.
     #[inline]
-    fn element<T, F: FnOnce(&mut String, T)>(&mut self, enc: F, value: T) -> &mut Self {
-        self.comma();
-        enc(&mut self.buf, value);
-        self
+    pub fn get(&mut self, key: &'k str) -> &PreparedKey {
+        let fmt = &self.fmt;
+        self.keys.entry(key).or_insert_with(|| fmt.prepare_key(key))
     }
+}
+
+/// Where a container records ranges for [`Object::record_ranges`].
+struct Recorder<'a> {
+    ranges: &'a mut Vec<(String, Range<usize>)>,
+    /// The entry of the container itself.
+    own: usize,
+    /// The entry of the value being written, whose end is not known yet.
+    open: Option<usize>,
+}
 
+impl<'a> Recorder<'a> {
     #[inline]
-    pub fn null(&mut self) -> &mut Self {
-        self.element(encode_null, ())
+    fn new(ranges: &'a mut Vec<(String, Range<usize>)>, start: usize) -> Self {
+        let own = ranges.len();
+        ranges.push((String::new(), start..start));
+        Recorder {
+            ranges,
+            own,
+            open: None,
+        }
     }
+    /// The recorder of the container which was just opened.
     #[inline]
-    pub fn bool(&mut self, value: bool) -> &mut Self {
-        self.element(encode_bool, value)
+    fn child(&mut self) -> Recorder<'_> {
+        Recorder {
+            own: self.open.unwrap_or(self.own),
+            ranges: self.ranges,
+            open: None,
+        }
     }
+    /// Starts the entry of the value under `token`, at `start`.
     #[inline]
-    pub fn number(&mut self, value: f64) -> &mut Self {
-        self.element(encode_number, value)
+    fn open(&mut self, start: usize, token: &str) {
+        let mut pointer = self.ranges[self.own].0.clone();
+        pointer.push('/');
+        for c in token.chars() {
+            match c {
+                '~' => pointer.push_str("~0"),
+                '/' => pointer.push_str("~1"),
+                c => pointer.push(c),
+            }
+        }
+        self.open = Some(self.ranges.len());
+        self.ranges.push((pointer, start..start));
     }
+    /// Ends the entry of the value being written, if any, at `end`.
     #[inline]
-    pub fn string(&mut self, value: &str) -> &mut Self {
-        self.element(encode_str, value)
+    fn end(&mut self, end: usize) {
+        if let Some(open) = self.open.take() {
+            self.ranges[open].1.end = end;
+        }
     }
+    /// Ends the entry of the container itself at `end`.
     #[inline]
-    pub fn object(&mut self) -> Object<'_> {
-        self.comma();
-        Object::new(self.buf)
+    fn close(&mut self, end: usize) {
+        self.ranges[self.own].1.end = end;
     }
+    /// Forgets the entries of values which started at or past `len`.
     #[inline]
-    pub fn array(&mut self) -> Array<'_> {
-        self.comma();
-        Array::new(self.buf)
+    fn forget_from(&mut self, len: usize) {
+        let kept = self.ranges.partition_point(|(_, range)| range.start < len);
+        self.ranges.truncate(kept);
+        self.open = self.open.filter(|&open| open < kept);
     }
 }
 
-impl Drop for Array<'_> {
+/// A fixed-width number written ahead of time, for values like lengths or
+/// checksums which are only known after more of the document is written.
+///
+/// Until filled, the placeholder holds `0`. Numbers are right-justified and
+/// padded with leading spaces, which is insignificant whitespace in JSON, so
+/// the document is valid before and after filling.
+#[must_use]
+#[derive(Debug)]
+pub struct Placeholder {
+    /// Where the number is, unless it was left out.
+    start: Option<usize>,
+    width: usize,
+}
+
+impl Placeholder {
     #[inline]
-    fn drop(&mut self) {
-        self.buf.push(']')
+    fn new(buf: &mut String, width: usize) -> Placeholder {
+        assert!(width > 0, "placeholder width must be positive");
+        let start = buf.len();
+        buf.extend(std::iter::repeat_n(' ', width - 1));
+        buf.push('0');
+        Placeholder {
+            start: Some(start),
+            width,
+        }
+    }
+    #[inline]
+    fn inert(width: usize) -> Placeholder {
+        assert!(width > 0, "placeholder width must be positive");
+        Placeholder { start: None, width }
+    }
+
+    /// Overwrites the placeholder in `buf` with `value`, if it was written.
+    ///
+    /// `buf` must be the buffer the placeholder was written to, with nothing
+    /// removed from it since then.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `value` has more digits than the placeholder's width.
+    pub fn fill(self, buf: &mut String, value: u64) {
+        let digits = value.to_string();
+        assert!(
+            digits.len() <= self.width,
+            "{value} does not fit into a placeholder of width {}",
+            self.width
+        );
+        let Some(start) = self.start else { return };
+        let range = start..start + self.width;
+        debug_assert!(buf[range.clone()].trim_start() == "0");
+        buf.replace_range(range, &format!("{digits:>width$}", width = self.width));
+    }
+}
+
+#[inline]
+#[track_caller]
+fn check_scratch(scratch: &str) {
+    debug_assert!(
+        !scratch.trim().is_empty() && text::unclosed(scratch) == 0,
+        "scratch is not a JSON value: {scratch:?}"
+    );
+}
+
+#[inline]
+fn check_finite(value: f64) -> Result<(), Error> {
+    if value.is_finite() {
+        Ok(())
+    } else {
+        Err(Error::NonFinite(value))
+    }
+}
+
+/// Removes the `_`s from `number`, each of which must sit between two digits,
+/// and checks that the rest is a JSON number.
+fn strip_separators(number: &str) -> Result<String, Error> {
+    let bytes = number.as_bytes();
+    let separated = |i: usize| {
+        i > 0 && bytes[i - 1].is_ascii_digit() && bytes.get(i + 1).is_some_and(u8::is_ascii_digit)
+    };
+    let separators_ok = (0..bytes.len()).all(|i| bytes[i] != b'_' || separated(i));
+    let stripped = number.replace('_', "");
+    if !separators_ok || !is_json_number(&stripped) {
+        return Err(Error::InvalidNumber(number.to_string()));
+    }
+    Ok(stripped)
+}
+
+/// Whether `s` matches JSON's number grammar,
+/// `-?(0|[1-9][0-9]*)(\.[0-9]+)?([eE][+-]?[0-9]+)?`.
+fn is_json_number(s: &str) -> bool {
+    fn digits(s: &[u8]) -> usize {
+        s.iter().take_while(|b| b.is_ascii_digit()).count()
+    }
+    let mut s = s.as_bytes();
+    if let [b'-', rest @ ..] = s {
+        s = rest;
+    }
+    match digits(s) {
+        0 => return false,
+        n if n > 1 && s[0] == b'0' => return false,
+        n => s = &s[n..],
+    }
+    if let [b'.', rest @ ..] = s {
+        match digits(rest) {
+            0 => return false,
+            n => s = &rest[n..],
+        }
+    }
+    if let [b'e' | b'E', rest @ ..] = s {
+        let rest = match rest {
+            [b'+' | b'-', rest @ ..] => rest,
+            rest => rest,
+        };
+        match digits(rest) {
+            0 => return false,
+            n => s = &rest[n..],
+        }
+    }
+    s.is_empty()
+}
+
+/// Whether `s` is valid as the contents of a JSON string: no `"`, `\` or
+/// control characters except in escape sequences.
+fn is_escaped(s: &str) -> bool {
+    let mut bytes = s.bytes();
+    while let Some(b) = bytes.next() {
+        match b {
+            b'"' | 0..=0x1F => return false,
+            b'\\' => match bytes.next() {
+                Some(b'"' | b'\\' | b'/' | b'b' | b'f' | b'n' | b'r' | b't') => (),
+                Some(b'u') => {
+                    if !(0..4).all(|_| bytes.next().is_some_and(|b| b.is_ascii_hexdigit())) {
+                        return false;
+                    }
+                }
+                _ => return false,
+            },
+            _ => (),
+        }
     }
+    true
+}
+
+#[inline]
+fn encode_preescaped(buf: &mut String, _: &Formatter, s: &str) {
+    debug_assert!(is_escaped(s), "string is not escaped: {s:?}");
+    buf.reserve(s.len() + 2);
+    buf.push('"');
+    buf.push_str(s);
+    buf.push('"');
 }
 
 #[inline]
-fn encode_null(buf: &mut String, (): ()) {
+fn encode_null(buf: &mut String, _: &Formatter, (): ()) {
     buf.push_str("null")
 }
 #[inline]
-fn encode_bool(buf: &mut String, value: bool) {
+fn encode_bool(buf: &mut String, _: &Formatter, value: bool) {
     buf.push_str(if value { "true" } else { "false" })
 }
 #[inline]
-fn encode_number(buf: &mut String, number: f64) {
+fn encode_number(buf: &mut String, fmt: &Formatter, number: f64) {
+    use std::fmt::Write;
+    // Up to 2^53, integral values print the same either way (above it,
+    // `Display` prints the shortest round-tripping digits padded with zeros),
+    // but integer formatting is much cheaper. `-0.0` is excluded, as it
+    // prints as `-0`.
+    const LIMIT: f64 = 9007199254740992.0; // 2^53
+    if let Some(float) = fmt.float {
+        return float(buf, number);
+    }
+    if fmt.big_as_strings && number.fract() == 0.0 && number.abs() >= LIMIT {
+        let _ = write!(buf, "\"{}\"", number);
+        return;
+    }
+    if fmt.plus_sign && number.is_sign_positive() && number.is_finite() {
+        buf.push('+');
+    }
+    if fmt.non_finite_tokens && !number.is_finite() {
+        buf.push_str(if number.is_nan() {
+            "NaN"
+        } else if number > 0.0 {
+            "Infinity"
+        } else {
+            "-Infinity"
+        });
+        return;
+    }
+    if number.fract() == 0.0
+        && (-LIMIT..=LIMIT).contains(&number)
+        && !(number == 0.0 && number.is_sign_negative())
+    {
+        encode_int(buf, number as i64)
+    } else {
+        let _ = write!(buf, "{}", number);
+    }
+    if fmt.always_fraction && number.fract() == 0.0 {
+        buf.push_str(".0");
+    }
+}
+/// Writes the number with the given bits in a 16-bit format, using the
+/// shortest decimal which lies in its rounding interval.
+fn encode_narrow(buf: &mut String, fmt: &Formatter, (bits, to_f64): (u16, fn(u16) -> f64)) {
+    let value = to_f64(bits);
+    let abs = bits & 0x7FFF;
+    if !value.is_finite() || abs == 0 {
+        return encode_number(buf, fmt, value);
+    }
+    // The neighbours, and so the midpoints, are exact in `f64`.
+    let prev = to_f64(abs - 1);
+    let next = match to_f64(abs + 1) {
+        next if next.is_finite() => next,
+        _ => 2.0 * value.abs() - prev,
+    };
+    let (lo, hi) = ((prev + value.abs()) / 2.0, (value.abs() + next) / 2.0);
+    // Ties round to an even mantissa.
+    let ties_in = abs % 2 == 0;
+    for sig in 1..17 {
+        let short: f64 = format!("{:.*e}", sig - 1, value.abs()).parse().unwrap();
+        if (lo < short && short < hi) || (ties_in && (short == lo || short == hi)) {
+            return encode_number(buf, fmt, short.copysign(value));
+        }
+    }
+    encode_number(buf, fmt, value)
+}
+#[inline]
+fn f16_to_f64(bits: u16) -> f64 {
+    let sign = if bits & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exp = i32::from(bits >> 10 & 0x1F);
+    let mantissa = f64::from(bits & 0x3FF);
+    sign * match exp {
+        0 => mantissa * 2f64.powi(-24),
+        0x1F if mantissa == 0.0 => f64::INFINITY,
+        0x1F => f64::NAN,
+        _ => (1024.0 + mantissa) * 2f64.powi(exp - 25),
+    }
+}
+#[inline]
+fn bf16_to_f64(bits: u16) -> f64 {
+    // A `bfloat16` is the upper half of an `f32`.
+    f32::from_bits(u32::from(bits) << 16).into()
+}
+#[inline]
+fn encode_fixed(buf: &mut String, fmt: &Formatter, (number, decimals): (f64, usize)) {
+    use std::fmt::Write;
+    if !number.is_finite() {
+        return encode_number(buf, fmt, number);
+    }
+    let _ = write!(buf, "{:.*}", decimals, number);
+}
+#[inline]
+fn encode_percent(buf: &mut String, fmt: &Formatter, (value, decimals): (f64, usize)) {
+    let mut text = format!("{:.*}%", decimals, value * 100.0);
+    if text.starts_with('-') && text.bytes().all(|b| matches!(b, b'-' | b'0' | b'.' | b'%')) {
+        text.remove(0);
+    }
+    encode_str(buf, fmt, &text);
+}
+fn encode_sig(buf: &mut String, fmt: &Formatter, (number, sig): (f64, usize)) {
+    use std::fmt::Write;
+    if !number.is_finite() {
+        return encode_number(buf, fmt, number);
+    }
+    let sig = sig.max(1);
+    let sci = format!("{:.*e}", sig - 1, number.abs());
+    let (mantissa, exp) = sci.split_once('e').unwrap();
+    let exp: i64 = exp.parse().unwrap();
+    if number.is_sign_negative() {
+        buf.push('-');
+    }
+    if exp < -6 || exp >= sig as i64 {
+        let sign = if exp < 0 { '-' } else { '+' };
+        let _ = write!(buf, "{mantissa}e{sign}{}", exp.abs());
+        return;
+    }
+    let digits = mantissa.replace('.', "");
+    if exp < 0 {
+        buf.push_str("0.");
+        buf.extend(std::iter::repeat_n('0', (-exp - 1) as usize));
+        buf.push_str(&digits);
+    } else {
+        let (int, frac) = digits.split_at(exp as usize + 1);
+        buf.push_str(int);
+        if !frac.is_empty() {
+            buf.push('.');
+            buf.push_str(frac);
+        }
+    }
+}
+#[inline]
+fn encode_range(buf: &mut String, _: &Formatter, range: Range<i64>) {
+    buf.push('[');
+    encode_int(buf, range.start);
+    buf.push(',');
+    encode_int(buf, range.end);
+    buf.push(']');
+}
+#[inline]
+fn encode_bytes_as_numbers(buf: &mut String, _: &Formatter, bytes: &[u8]) {
+    buf.reserve(bytes.len() * 4 + 2);
+    buf.push('[');
+    for (i, &b) in bytes.iter().enumerate() {
+        if i > 0 {
+            buf.push(',');
+        }
+        encode_int(buf, b.into());
+    }
+    buf.push(']');
+}
+#[inline]
+fn encode_position(buf: &mut String, fmt: &Formatter, ([lon, lat], decimals): ([f64; 2], usize)) {
+    buf.push('[');
+    encode_fixed(buf, fmt, (lon, decimals));
+    buf.push(',');
+    encode_fixed(buf, fmt, (lat, decimals));
+    buf.push(']');
+}
+#[inline]
+fn encode_coordinates(
+    buf: &mut String,
+    fmt: &Formatter,
+    (positions, decimals): (&[[f64; 2]], usize),
+) {
+    buf.push('[');
+    for (i, &position) in positions.iter().enumerate() {
+        if i > 0 {
+            buf.push(',');
+        }
+        encode_position(buf, fmt, (position, decimals));
+    }
+    buf.push(']');
+}
+#[inline]
+fn encode_date(buf: &mut String, _: &Formatter, (year, month, day): (i32, u8, u8)) {
+    use std::fmt::Write;
+    debug_assert!((0..=9999).contains(&year), "year {year} out of range");
+    let leap = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+    let days = match month {
+        1 | 3 | 5 | 7 | 8 | 10 | 12 => 31,
+        4 | 6 | 9 | 11 => 30,
+        2 if leap => 29,
+        2 => 28,
+        _ => 0,
+    };
+    debug_assert!(
+        (1..=days).contains(&day),
+        "invalid date {year}-{month:02}-{day:02}"
+    );
+    let _ = write!(buf, "\"{year:04}-{month:02}-{day:02}\"");
+}
+#[inline]
+fn encode_duration_iso8601(buf: &mut String, fmt: &Formatter, duration: Duration) {
+    use std::fmt::Write;
+    let secs = duration.as_secs();
+    let nanos = duration.subsec_nanos();
+    let mut text = String::from("PT");
+    if secs >= 3600 {
+        let _ = write!(text, "{}H", secs / 3600);
+    }
+    if secs % 3600 >= 60 {
+        let _ = write!(text, "{}M", secs % 3600 / 60);
+    }
+    if !secs.is_multiple_of(60) || nanos != 0 || secs == 0 {
+        let _ = write!(text, "{}", secs % 60);
+        if nanos != 0 {
+            let frac = format!("{nanos:09}");
+            let _ = write!(text, ".{}", frac.trim_end_matches('0'));
+        }
+        text.push('S');
+    }
+    encode_str(buf, fmt, &text);
+}
+#[inline]
+fn encode_cidr(buf: &mut String, _: &Formatter, (addr, prefix): (IpAddr, u8)) {
+    use std::fmt::Write;
+    check_prefix(addr, prefix);
+    // Addresses never need escaping.
+    let _ = write!(buf, "\"{addr}/{prefix}\"");
+}
+#[inline]
+fn encode_cidr_object(buf: &mut String, _: &Formatter, (addr, prefix): (IpAddr, u8)) {
     use std::fmt::Write;
-    let _ = write!(buf, "{}", number);
+    check_prefix(addr, prefix);
+    let _ = write!(buf, "{{\"addr\":\"{addr}\",\"prefix\":{prefix}}}");
+}
+#[inline]
+fn check_prefix(addr: IpAddr, prefix: u8) {
+    let max = if addr.is_ipv4() { 32 } else { 128 };
+    assert!(prefix <= max, "prefix /{prefix} is too long for {addr}");
+}
+#[inline]
+fn encode_bitset(buf: &mut String, _: &Formatter, bits: &[bool]) {
+    const DIGITS: &[u8; 16] = b"0123456789abcdef";
+    buf.push_str("{\"len\":");
+    encode_int(buf, bits.len() as i64);
+    buf.push_str(",\"hex\":\"");
+    for byte in bits.chunks(8) {
+        let byte = byte
+            .iter()
+            .enumerate()
+            .fold(0u8, |acc, (i, &bit)| acc | (u8::from(bit) << (7 - i)));
+        buf.push(DIGITS[usize::from(byte >> 4)] as char);
+        buf.push(DIGITS[usize::from(byte & 0xF)] as char);
+    }
+    buf.push_str("\"}");
+}
+fn encode_int_radix(buf: &mut String, fmt: &Formatter, (value, radix, prefix): (i64, u32, &str)) {
+    assert!((2..=36).contains(&radix), "radix {radix} is not in 2..=36");
+    let mut digits = [0u8; 64];
+    let mut start = digits.len();
+    let mut rest = value.unsigned_abs();
+    loop {
+        start -= 1;
+        let digit = (rest % u64::from(radix)) as u32;
+        digits[start] = char::from_digit(digit, radix).unwrap() as u8;
+        rest /= u64::from(radix);
+        if rest == 0 {
+            break;
+        }
+    }
+    buf.push('"');
+    if value < 0 {
+        buf.push('-');
+    }
+    escape_str(buf, fmt, prefix);
+    buf.push_str(std::str::from_utf8(&digits[start..]).unwrap());
+    buf.push('"');
+}
+#[inline]
+fn encode_run_length(buf: &mut String, fmt: &Formatter, numbers: &[f64]) {
+    buf.push('[');
+    // Bitwise equality keeps `0` and `-0` apart; `NaN`s just don't repeat.
+    let runs = numbers.chunk_by(|l, r| l.to_bits() == r.to_bits() && !l.is_nan());
+    for (i, run) in runs.enumerate() {
+        if i > 0 {
+            buf.push(',');
+        }
+        buf.push('[');
+        encode_number(buf, fmt, run[0]);
+        buf.push(',');
+        encode_int(buf, run.len() as i64);
+        buf.push(']');
+    }
+    buf.push(']');
+}
+#[inline]
+fn encode_int(buf: &mut String, value: i64) {
+    let mut digits = [0u8; 20];
+    let mut i = digits.len();
+    let mut n = value.unsigned_abs();
+    loop {
+        i -= 1;
+        digits[i] = b'0' + (n % 10) as u8;
+        n /= 10;
+        if n == 0 {
+            break;
+        }
+    }
+    if value < 0 {
+        buf.push('-');
+    }
+    buf.extend(digits[i..].iter().map(|&d| d as char));
 }
 
 #[inline]
-fn encode_str(buf: &mut String, s: &str) {
+fn encode_str(buf: &mut String, fmt: &Formatter, s: &str) {
     buf.reserve(s.len() + 2);
     buf.push('\"');
-    if s.bytes()
-        .all(|b| 0x1F < b && b != b'"' && b != b'\\' && b < 0x7F)
-    {
+    escape_str(buf, fmt, s);
+    buf.push('\"');
+}
+
+#[inline]
+fn encode_chars<I: IntoIterator<Item = char>>(buf: &mut String, fmt: &Formatter, chars: I) {
+    buf.push('\"');
+    let mut sink = Sink::String(buf);
+    for c in chars {
+        escape_char(&mut sink, fmt, c);
+    }
+    buf.push('\"');
+}
+
+/// Escapes everything written to it into `buf`.
+struct Escaper<'a> {
+    buf: &'a mut String,
+    fmt: &'a Formatter,
+}
+
+impl fmt::Write for Escaper<'_> {
+    #[inline]
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        escape_str(self.buf, self.fmt, s);
+        Ok(())
+    }
+}
+
+#[inline]
+fn escape_str(buf: &mut String, fmt: &Formatter, s: &str) {
+    if is_plain_ascii(s) {
+        #[cfg(feature = "stats")]
+        stats::record_fast();
         buf.push_str(s)
     } else {
-        slow_path(buf, s)
+        slow_path(buf, fmt, s)
     }
-    buf.push('\"');
 
     #[inline(never)]
-    fn slow_path(buf: &mut String, s: &str) {
+    fn slow_path(buf: &mut String, fmt: &Formatter, s: &str) {
+        #[cfg(feature = "stats")]
+        stats::record_slow(s.len());
+        let mut sink = Sink::String(buf);
         for c in s.chars() {
-            if (c as u32) < 256 {
-                let b = c as u8;
-                match b {
-                    b'\\' | b'"' => push_escape(buf, c),
-                    b'\n' => push_escape(buf, 'n'),
-                    b'\r' => push_escape(buf, 'r'),
-                    b'\t' => push_escape(buf, 't'),
-                    0..=0x1F | 0x7F..=0x9F => {
-                        push_escape(buf, 'u');
-                        buf.push_str("00");
-                        buf.push(hex(b >> 4));
-                        buf.push(hex(b & 0xF));
-                    }
-                    _ => buf.push(c),
-                }
-            } else {
-                buf.push(c)
+            escape_char(&mut sink, fmt, c);
+        }
+    }
+}
+
+/// Whether `s` is printable ASCII without `"` and `\`, which never needs
+/// escaping.
+#[inline]
+fn is_plain_ascii(s: &str) -> bool {
+    s.bytes()
+        .all(|b| 0x1F < b && b != b'"' && b != b'\\' && b < 0x7F)
+}
+
+/// The output of [`escape_char`]. An enum rather than a trait, so that
+/// escaping is compiled once however many backends there are.
+enum Sink<'a> {
+    String(&'a mut String),
+    Slice(SliceCursor<'a>),
+}
+
+impl Sink<'_> {
+    #[inline]
+    fn push(&mut self, c: char) {
+        match self {
+            Sink::String(buf) => buf.push(c),
+            Sink::Slice(cursor) => cursor.push_str(c.encode_utf8(&mut [0; 4])),
+        }
+    }
+    #[inline]
+    fn push_str(&mut self, s: &str) {
+        match self {
+            Sink::String(buf) => buf.push_str(s),
+            Sink::Slice(cursor) => cursor.push_str(s),
+        }
+    }
+    #[inline]
+    fn overflowed(&self) -> bool {
+        matches!(self, Sink::Slice(cursor) if cursor.overflow)
+    }
+}
+
+/// Writes to the start of `dst` until something does not fit.
+struct SliceCursor<'a> {
+    dst: &'a mut [u8],
+    len: usize,
+    overflow: bool,
+}
+
+impl SliceCursor<'_> {
+    #[inline]
+    fn push_str(&mut self, s: &str) {
+        match self.dst.get_mut(self.len..self.len + s.len()) {
+            Some(dst) if !self.overflow => {
+                dst.copy_from_slice(s.as_bytes());
+                self.len += s.len();
+            }
+            _ => self.overflow = true,
+        }
+    }
+}
+
+#[inline]
+fn escape_char(buf: &mut Sink<'_>, fmt: &Formatter, c: char) {
+    if (c as u32) < 256 {
+        let b = c as u8;
+        if b == b'\t' {
+            if let Some(width) = fmt.tab_width {
+                (0..width).for_each(|_| buf.push(' '));
+                return;
+            }
+        }
+        if b < 0x20 && fmt.strip_controls {
+            if matches!(b, b'\t' | b'\n' | b'\r') {
+                buf.push(' ');
+            }
+            return;
+        }
+        match b {
+            b'\\' | b'"' => push_escape(buf, c),
+            b'\n' => push_escape(buf, 'n'),
+            b'\r' => push_escape(buf, 'r'),
+            b'\t' => push_escape(buf, 't'),
+            0..=0x1F | 0x7F..=0x9F if b < 0x20 || fmt.escape_c1 => {
+                push_escape(buf, 'u');
+                buf.push_str("00");
+                buf.push(hex(fmt, b >> 4));
+                buf.push(hex(fmt, b & 0xF));
             }
+            _ => buf.push(c),
         }
+    } else if fmt.jsonp_safe && matches!(c, '\u{2028}' | '\u{2029}') {
+        push_escape(buf, 'u');
+        buf.push_str(if c == '\u{2028}' { "2028" } else { "2029" });
+    } else {
+        buf.push(c)
     }
 
     #[inline]
-    fn push_escape(buf: &mut String, c: char) {
+    fn push_escape(buf: &mut Sink<'_>, c: char) {
         buf.push('\\');
         buf.push(c);
     }
 
     #[inline]
-    fn hex(b: u8) -> char {
-        (b"0123456789ABCDEF"[(b & 0xF) as usize]) as char
+    fn hex(fmt: &Formatter, b: u8) -> char {
+        let digits = if fmt.lowercase_hex {
+            b"0123456789abcdef"
+        } else {
+            b"0123456789ABCDEF"
+        };
+        (digits[(b & 0xF) as usize]) as char
     }
 }
@@ -0,0 +1,182 @@
+use std::mem;
+
+use crate::{Formatter, Object};
+
+/// An object nested in a parent `P`, which [`NestedObject::done`] hands back,
+/// started with [`Object::nested`]. Unlike with [`Object::object`], the
+/// parent can be continued in the same chain:
+///
+/// ```
+/// let mut buf = String::new();
+/// write_json::object(&mut buf)
+///     .nested("user")
+///     .string("name", "Peter")
+///     .done()
+///     .number("version", 1.0);
+/// assert_eq!(buf, r#"{"user":{"name":"Peter"},"version":1}"#);
+/// ```
+///
+/// A `NestedObject` which is dropped rather than finished with `done` closes
+/// itself, giving control back to the parent just like an [`Object`] does.
+pub struct NestedObject<P: Parent> {
+    /// Always `Some`, until `done` takes it.
+    parent: Option<P>,
+    state: State,
+}
+
+/// An open object without its buffer.
+#[derive(Clone, Copy)]
+struct State {
+    fmt: Formatter,
+    depth: usize,
+    first: bool,
+    len: usize,
+    cut: Option<usize>,
+    start: usize,
+    last: usize,
+    exceeded: Option<usize>,
+    skipped: Option<(usize, bool, usize)>,
+}
+
+impl State {
+    /// Takes `obj` apart, leaving it open.
+    #[inline]
+    fn take(obj: Object<'_>) -> State {
+        let state = State {
+            fmt: obj.fmt,
+            depth: obj.depth,
+            first: obj.first,
+            len: obj.len,
+            cut: obj.cut,
+            start: obj.start,
+            last: obj.last,
+            exceeded: obj.exceeded,
+            skipped: obj.skipped,
+        };
+        mem::forget(obj);
+        state
+    }
+    #[inline]
+    fn object(self, buf: &mut String) -> Object<'_> {
+        Object {
+            buf,
+            fmt: self.fmt,
+            depth: self.depth,
+            first: self.first,
+            prefix: "",
+            len: self.len,
+            cut: self.cut,
+            start: self.start,
+            last: self.last,
+            exceeded: self.exceeded,
+            skipped: self.skipped,
+            omitted: None,
+            ranges: None,
+        }
+    }
+}
+
+mod sealed {
+    use crate::Object;
+
+    /// What a [`NestedObject`](crate::NestedObject) can be nested in.
+    pub trait Parent {
+        #[doc(hidden)]
+        fn edit<R, F: FnOnce(&mut Object<'_>) -> R>(&mut self, f: F) -> R;
+    }
+}
+
+use sealed::Parent;
+
+impl<'a> Parent for &mut Object<'a> {
+    #[inline]
+    fn edit<R, F: FnOnce(&mut Object<'_>) -> R>(&mut self, f: F) -> R {
+        f(self)
+    }
+}
+
+impl<P: Parent> Parent for NestedObject<P> {
+    #[inline]
+    fn edit<R, F: FnOnce(&mut Object<'_>) -> R>(&mut self, f: F) -> R {
+        let state = self.state;
+        let (res, state) = self.parent.as_mut().unwrap().edit(|parent| {
+            let mut obj = state.object(parent.buf);
+            let res = f(&mut obj);
+            (res, State::take(obj))
+        });
+        self.state = state;
+        res
+    }
+}
+
+impl<P: Parent> NestedObject<P> {
+    #[inline]
+    pub(crate) fn new(mut parent: P, key: &str) -> Self {
+        let state = parent.edit(|obj| State::take(obj.object(key)));
+        NestedObject {
+            parent: Some(parent),
+            state,
+        }
+    }
+
+    #[inline]
+    pub fn null<K: AsRef<str>>(mut self, key: K) -> Self {
+        self.edit(|obj| {
+            obj.null(key);
+        });
+        self
+    }
+    #[inline]
+    pub fn bool<K: AsRef<str>>(mut self, key: K, value: bool) -> Self {
+        self.edit(|obj| {
+            obj.bool(key, value);
+        });
+        self
+    }
+    #[inline]
+    pub fn number<K: AsRef<str>>(mut self, key: K, value: f64) -> Self {
+        self.edit(|obj| {
+            obj.number(key, value);
+        });
+        self
+    }
+    #[inline]
+    pub fn string<K: AsRef<str>>(mut self, key: K, value: &str) -> Self {
+        self.edit(|obj| {
+            obj.string(key, value);
+        });
+        self
+    }
+    /// Lets `f` write fields with any of the [`Object`] methods.
+    #[inline]
+    pub fn fields<F: FnOnce(&mut Object<'_>)>(mut self, f: F) -> Self {
+        self.edit(f);
+        self
+    }
+    /// Starts an object nested in this one, under `key`.
+    #[inline]
+    pub fn nested<K: AsRef<str>>(self, key: K) -> NestedObject<Self> {
+        NestedObject::new(self, key.as_ref())
+    }
+    /// Closes this object and returns its parent, to continue writing to it.
+    #[inline]
+    pub fn done(mut self) -> P {
+        self.close();
+        self.parent.take().unwrap()
+    }
+
+    #[inline]
+    fn close(&mut self) {
+        let state = self.state;
+        if let Some(parent) = &mut self.parent {
+            parent.edit(|parent| drop(state.object(parent.buf)));
+        }
+    }
+}
+
+impl<P: Parent> Drop for NestedObject<P> {
+    #[inline]
+    fn drop(&mut self) {
+        self.close()
+    }
+}
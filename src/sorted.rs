@@ -0,0 +1,207 @@
+use std::{cmp::Ordering, ops::Range};
+
+use crate::{encode_bool, encode_null, encode_number, encode_str, Array, Formatter, Object};
+
+type KeyCmp<'a> = Box<dyn Fn(&str, &str) -> Ordering + 'a>;
+
+/// An object which buffers its fields and writes them ordered by key when
+/// dropped.
+///
+/// Keys are compared as raw, unescaped strings, that is by their UTF-8
+/// bytes, and fields with equal keys keep the order in which they were
+/// written. Unicode normalization is not applied, so `"\u{e9}"` and
+/// `"e\u{301}"` (both `é`) are different keys; pass a comparator which
+/// normalizes to [`sorted_object_by`](crate::sorted_object_by) if the keys may
+/// come in either form.
+pub struct SortedObject<'a> {
+    buf: &'a mut String,
+    fmt: Formatter,
+    /// Raw keys and serialized values, back to back, so that buffering
+    /// allocates only this and `fields`.
+    scratch: String,
+    fields: Vec<Field>,
+    cmp: Option<KeyCmp<'a>>,
+}
+
+struct Field {
+    key: Range<usize>,
+    value: Range<usize>,
+}
+
+impl<'a> SortedObject<'a> {
+    #[inline]
+    pub(crate) fn new(buf: &'a mut String) -> Self {
+        SortedObject {
+            buf,
+            fmt: Formatter::new(),
+            scratch: String::new(),
+            fields: Vec::new(),
+            cmp: None,
+        }
+    }
+    #[inline]
+    pub(crate) fn with_cmp(buf: &'a mut String, cmp: KeyCmp<'a>) -> Self {
+        SortedObject {
+            buf,
+            fmt: Formatter::new(),
+            scratch: String::new(),
+            fields: Vec::new(),
+            cmp: Some(cmp),
+        }
+    }
+    #[inline]
+    fn value_buf(&mut self, key: &str) -> &mut String {
+        let start = self.scratch.len();
+        self.scratch.push_str(key);
+        let end = self.scratch.len();
+        // The value's end is only known once the next field starts.
+        self.fields.push(Field {
+            key: start..end,
+            value: end..end,
+        });
+        &mut self.scratch
+    }
+    #[inline]
+    fn field<T, F>(&mut self, key: &str, enc: F, value: T) -> &mut Self
+    where
+        F: FnOnce(&mut String, &Formatter, T),
+    {
+        let fmt = self.fmt;
+        enc(self.value_buf(key), &fmt, value);
+        self
+    }
+
+    #[inline]
+    pub fn null<K: AsRef<str>>(&mut self, key: K) -> &mut Self {
+        self.field(key.as_ref(), encode_null, ())
+    }
+    #[inline]
+    pub fn bool<K: AsRef<str>>(&mut self, key: K, value: bool) -> &mut Self {
+        self.field(key.as_ref(), encode_bool, value)
+    }
+    #[inline]
+    pub fn number<K: AsRef<str>>(&mut self, key: K, value: f64) -> &mut Self {
+        self.field(key.as_ref(), encode_number, value)
+    }
+    #[inline]
+    pub fn string<K: AsRef<str>>(&mut self, key: K, value: &str) -> &mut Self {
+        self.field(key.as_ref(), encode_str, value)
+    }
+    /// Starts a nested object under `key`. The nested object itself is not
+    /// sorted.
+    #[inline]
+    pub fn object<K: AsRef<str>>(&mut self, key: K) -> Object<'_> {
+        let fmt = self.fmt;
+        Object::new(self.value_buf(key.as_ref()), fmt, 1)
+    }
+    #[inline]
+    pub fn array<K: AsRef<str>>(&mut self, key: K) -> Array<'_> {
+        let fmt = self.fmt;
+        Array::new(self.value_buf(key.as_ref()), fmt, 1)
+    }
+}
+
+impl Drop for SortedObject<'_> {
+    fn drop(&mut self) {
+        let mut end = self.scratch.len();
+        for field in self.fields.iter_mut().rev() {
+            field.value.end = end;
+            end = field.key.start;
+        }
+        let scratch = &self.scratch;
+        let key = |field: &Field| &scratch[field.key.clone()];
+        match &self.cmp {
+            Some(cmp) => self.fields.sort_by(|l, r| cmp(key(l), key(r))),
+            None => self.fields.sort_by(|l, r| key(l).cmp(key(r))),
+        }
+        let mut obj = Object::new(self.buf, self.fmt, 0);
+        for field in &self.fields {
+            let value = &scratch[field.value.clone()];
+            obj.field(key(field), |buf, _, value| buf.push_str(value), value);
+        }
+    }
+}
+
+/// An array which buffers its elements and writes them ordered by their
+/// serialized bytes when dropped, for canonical output of set-like data.
+pub struct SortedArray<'a> {
+    buf: &'a mut String,
+    fmt: Formatter,
+    /// Serialized elements, back to back.
+    scratch: String,
+    elements: Vec<Range<usize>>,
+}
+
+impl<'a> SortedArray<'a> {
+    #[inline]
+    pub(crate) fn new(buf: &'a mut String) -> Self {
+        SortedArray {
+            buf,
+            fmt: Formatter::new(),
+            scratch: String::new(),
+            elements: Vec::new(),
+        }
+    }
+    #[inline]
+    fn element_buf(&mut self) -> &mut String {
+        let start = self.scratch.len();
+        self.elements.push(start..start);
+        &mut self.scratch
+    }
+    #[inline]
+    fn element<T, F>(&mut self, enc: F, value: T) -> &mut Self
+    where
+        F: FnOnce(&mut String, &Formatter, T),
+    {
+        let fmt = self.fmt;
+        enc(self.element_buf(), &fmt, value);
+        self
+    }
+
+    #[inline]
+    pub fn null(&mut self) -> &mut Self {
+        self.element(encode_null, ())
+    }
+    #[inline]
+    pub fn bool(&mut self, value: bool) -> &mut Self {
+        self.element(encode_bool, value)
+    }
+    #[inline]
+    pub fn number(&mut self, value: f64) -> &mut Self {
+        self.element(encode_number, value)
+    }
+    #[inline]
+    pub fn string(&mut self, value: &str) -> &mut Self {
+        self.element(encode_str, value)
+    }
+    /// Starts a nested object element, which is sorted with the others once
+    /// complete. The nested object itself is not sorted.
+    #[inline]
+    pub fn object(&mut self) -> Object<'_> {
+        let fmt = self.fmt;
+        Object::new(self.element_buf(), fmt, 1)
+    }
+    #[inline]
+    pub fn array(&mut self) -> Array<'_> {
+        let fmt = self.fmt;
+        Array::new(self.element_buf(), fmt, 1)
+    }
+}
+
+impl Drop for SortedArray<'_> {
+    fn drop(&mut self) {
+        let mut end = self.scratch.len();
+        for element in self.elements.iter_mut().rev() {
+            element.end = end;
+            end = element.start;
+        }
+        let scratch = &self.scratch;
+        self.elements
+            .sort_by(|l, r| scratch[l.clone()].cmp(&scratch[r.clone()]));
+        let mut arr = Array::new(self.buf, self.fmt, 0);
+        for element in &self.elements {
+            let element = &scratch[element.clone()];
+            arr.element(|buf, _, element| buf.push_str(element), element);
+        }
+    }
+}
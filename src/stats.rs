@@ -0,0 +1,51 @@
+use std::cell::Cell;
+
+/// Counters of how strings were escaped on the current thread, for profiling
+/// escaping overhead. Only available with the `stats` feature.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct EscapeStats {
+    /// Strings copied verbatim, as they needed no escaping.
+    pub fast_hits: u64,
+    /// Strings escaped char by char.
+    pub slow_hits: u64,
+    /// Bytes of input which went through the char by char path.
+    pub bytes_escaped: u64,
+}
+
+thread_local! {
+    static STATS: Cell<EscapeStats> = const {
+        Cell::new(EscapeStats { fast_hits: 0, slow_hits: 0, bytes_escaped: 0 })
+    };
+}
+
+/// Returns the counters accumulated on this thread since the last
+/// [`reset_escape_stats`].
+#[inline]
+pub fn escape_stats() -> EscapeStats {
+    STATS.with(Cell::get)
+}
+
+/// Zeroes the counters of this thread.
+#[inline]
+pub fn reset_escape_stats() {
+    STATS.with(|it| it.set(EscapeStats::default()))
+}
+
+#[inline]
+pub(crate) fn record_fast() {
+    STATS.with(|it| {
+        let mut stats = it.get();
+        stats.fast_hits += 1;
+        it.set(stats)
+    })
+}
+
+#[inline]
+pub(crate) fn record_slow(len: usize) {
+    STATS.with(|it| {
+        let mut stats = it.get();
+        stats.slow_hits += 1;
+        stats.bytes_escaped += len as u64;
+        it.set(stats)
+    })
+}
@@ -0,0 +1,297 @@
+use std::{mem::ManuallyDrop, ops::ControlFlow};
+
+use crate::{Array, Formatter, Object};
+
+/// An object which hands the buffer to a callback whenever it grows past a
+/// threshold, and then clears it.
+///
+/// Flushing only happens between top-level fields, so nested containers are
+/// always flushed whole or not at all. Call [`StreamingObject::finish`] (or
+/// drop the object) to write the closing `}` and flush the remainder.
+pub struct StreamingObject<'a, F: FnMut(&str)> {
+    obj: ManuallyDrop<Object<'a>>,
+    threshold: usize,
+    flush: F,
+}
+
+impl<'a, F: FnMut(&str)> StreamingObject<'a, F> {
+    #[inline]
+    pub(crate) fn new(buf: &'a mut String, threshold: usize, flush: F) -> Self {
+        StreamingObject {
+            obj: ManuallyDrop::new(Object::new(buf, Formatter::new(), 0)),
+            threshold,
+            flush,
+        }
+    }
+    /// Changes the threshold for the following writes, for example once a
+    /// client is known to be slow.
+    #[inline]
+    pub fn set_flush_threshold(&mut self, threshold: usize) -> &mut Self {
+        self.threshold = threshold;
+        self
+    }
+    #[inline]
+    fn maybe_flush(&mut self) -> &mut Self {
+        if self.obj.buf.len() >= self.threshold {
+            self.flush_now();
+        }
+        self
+    }
+    #[inline]
+    fn flush_now(&mut self) {
+        (self.flush)(self.obj.buf);
+        self.obj.buf.clear();
+    }
+
+    #[inline]
+    pub fn null<K: AsRef<str>>(&mut self, key: K) -> &mut Self {
+        self.maybe_flush().obj.null(key);
+        self.maybe_flush()
+    }
+    #[inline]
+    pub fn bool<K: AsRef<str>>(&mut self, key: K, value: bool) -> &mut Self {
+        self.maybe_flush().obj.bool(key, value);
+        self.maybe_flush()
+    }
+    #[inline]
+    pub fn number<K: AsRef<str>>(&mut self, key: K, value: f64) -> &mut Self {
+        self.maybe_flush().obj.number(key, value);
+        self.maybe_flush()
+    }
+    #[inline]
+    pub fn string<K: AsRef<str>>(&mut self, key: K, value: &str) -> &mut Self {
+        self.maybe_flush().obj.string(key, value);
+        self.maybe_flush()
+    }
+    /// Starts a nested object under `key`. It is flushed on the next
+    /// top-level write or on finish.
+    #[inline]
+    pub fn object<K: AsRef<str>>(&mut self, key: K) -> Object<'_> {
+        self.maybe_flush().obj.object(key)
+    }
+    /// Starts a nested array under `key`. It is flushed on the next
+    /// top-level write or on finish.
+    #[inline]
+    pub fn array<K: AsRef<str>>(&mut self, key: K) -> Array<'_> {
+        self.maybe_flush().obj.array(key)
+    }
+
+    /// Writes the closing `}` and flushes whatever is left in the buffer.
+    #[inline]
+    pub fn finish(self) {}
+}
+
+impl<F: FnMut(&str)> Drop for StreamingObject<'_, F> {
+    fn drop(&mut self) {
+        self.obj.close();
+        self.flush_now();
+    }
+}
+
+/// An array which hands the buffer to a callback whenever it grows past a
+/// threshold, and then clears it, like [`StreamingObject`]. The callback
+/// returns [`ControlFlow::Break`] to apply backpressure, for example when a
+/// bounded channel is full.
+///
+/// Once paused by the callback, writes are refused, returning `Break`
+/// without writing anything, until [`StreamingArray::resume`]. Finishing
+/// (or dropping) the array always writes the closing `]` and flushes, paused
+/// or not.
+pub struct StreamingArray<'a, F: FnMut(&str) -> ControlFlow<()>> {
+    buf: &'a mut String,
+    len: usize,
+    threshold: usize,
+    flush: F,
+    paused: bool,
+}
+
+impl<'a, F: FnMut(&str) -> ControlFlow<()>> StreamingArray<'a, F> {
+    #[inline]
+    pub(crate) fn new(buf: &'a mut String, threshold: usize, flush: F) -> Self {
+        buf.push('[');
+        StreamingArray {
+            buf,
+            len: 0,
+            threshold,
+            flush,
+            paused: false,
+        }
+    }
+    #[inline]
+    fn flush_now(&mut self) -> ControlFlow<()> {
+        let flow = (self.flush)(self.buf);
+        self.buf.clear();
+        self.paused = flow.is_break();
+        flow
+    }
+
+    /// Lets `f` append elements to the array, and flushes if the buffer
+    /// has grown past the threshold. Returns `Break` if the array is paused,
+    /// either already, in which case `f` isn't called, or by this flush.
+    #[inline]
+    pub fn write<G: FnOnce(&mut Array<'_>)>(&mut self, f: G) -> ControlFlow<()> {
+        if self.paused {
+            return ControlFlow::Break(());
+        }
+        let mut arr = Array {
+            buf: self.buf,
+            fmt: Formatter::new(),
+            depth: 0,
+            len: self.len,
+            cut: None,
+            start: None,
+            last: 0,
+            exceeded: None,
+            ranges: None,
+        };
+        f(&mut arr);
+        self.len = arr.len;
+        arr.suspend();
+        if self.buf.len() >= self.threshold {
+            return self.flush_now();
+        }
+        ControlFlow::Continue(())
+    }
+    #[inline]
+    pub fn null(&mut self) -> ControlFlow<()> {
+        self.write(|arr| {
+            arr.null();
+        })
+    }
+    #[inline]
+    pub fn bool(&mut self, value: bool) -> ControlFlow<()> {
+        self.write(|arr| {
+            arr.bool(value);
+        })
+    }
+    #[inline]
+    pub fn number(&mut self, value: f64) -> ControlFlow<()> {
+        self.write(|arr| {
+            arr.number(value);
+        })
+    }
+    #[inline]
+    pub fn string(&mut self, value: &str) -> ControlFlow<()> {
+        self.write(|arr| {
+            arr.string(value);
+        })
+    }
+
+    /// Whether the last flush returned `Break`.
+    #[inline]
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+    /// Accepts writes again after the callback paused the array.
+    #[inline]
+    pub fn resume(&mut self) -> &mut Self {
+        self.paused = false;
+        self
+    }
+    /// Writes the closing `]` and flushes whatever is left in the buffer.
+    #[inline]
+    pub fn finish(self) {}
+}
+
+impl<F: FnMut(&str) -> ControlFlow<()>> Drop for StreamingArray<'_, F> {
+    fn drop(&mut self) {
+        self.buf.push(']');
+        let _ = self.flush_now();
+    }
+}
+
+/// A top-level array which owns its buffer, so that finished output can be
+/// taken out between writes, for example to send it to an async sink.
+///
+/// Elements are written with [`ChunkedArray::write`]; concatenating every
+/// [`ChunkedArray::take_chunk`] and the result of [`ChunkedArray::finish`]
+/// gives the whole document.
+pub struct ChunkedArray {
+    buf: String,
+    len: usize,
+}
+
+impl ChunkedArray {
+    #[inline]
+    pub(crate) fn new() -> Self {
+        ChunkedArray {
+            buf: String::from("["),
+            len: 0,
+        }
+    }
+    /// Lets `f` append elements to the array.
+    #[inline]
+    pub fn write<F: FnOnce(&mut Array<'_>)>(&mut self, f: F) -> &mut Self {
+        let mut arr = Array {
+            buf: &mut self.buf,
+            fmt: Formatter::new(),
+            depth: 0,
+            len: self.len,
+            cut: None,
+            start: None,
+            last: 0,
+            exceeded: None,
+            ranges: None,
+        };
+        f(&mut arr);
+        self.len = arr.len;
+        arr.suspend();
+        self
+    }
+    /// Returns the output written so far and clears the buffer.
+    #[inline]
+    pub fn take_chunk(&mut self) -> String {
+        std::mem::take(&mut self.buf)
+    }
+    /// Writes the closing `]` and returns the last chunk.
+    #[inline]
+    pub fn finish(self) -> String {
+        let mut buf = self.buf;
+        buf.push(']');
+        buf
+    }
+}
+
+/// Writes newline-delimited JSON (JSON Lines), one record per line.
+///
+/// A record whose serialization fails is rolled back, so the buffer always
+/// ends at a record boundary.
+pub struct JsonLines<'a> {
+    buf: &'a mut String,
+}
+
+impl<'a> JsonLines<'a> {
+    #[inline]
+    pub(crate) fn new(buf: &'a mut String) -> Self {
+        JsonLines { buf }
+    }
+    /// Lets `write` write one record, followed by a newline.
+    ///
+    /// `write` must write a single JSON value without newlines, so not in
+    /// [`pretty`](Formatter::pretty) mode.
+    #[inline]
+    pub fn record<F: FnOnce(&mut String)>(&mut self, write: F) -> &mut Self {
+        write(self.buf);
+        self.buf.push('\n');
+        self
+    }
+    /// Like [`JsonLines::record`], but if `write` fails, removes whatever it
+    /// wrote and returns the error.
+    #[inline]
+    pub fn try_record<E, F>(&mut self, write: F) -> Result<&mut Self, E>
+    where
+        F: FnOnce(&mut String) -> Result<(), E>,
+    {
+        let start = self.buf.len();
+        match write(self.buf) {
+            Ok(()) => {
+                self.buf.push('\n');
+                Ok(self)
+            }
+            Err(err) => {
+                self.buf.truncate(start);
+                Err(err)
+            }
+        }
+    }
+}
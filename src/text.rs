@@ -0,0 +1,170 @@
+//! Utilities operating on already serialized JSON text.
+
+/// Removes insignificant whitespace from `json`, leaving string literals
+/// untouched.
+///
+/// This does not parse or validate `json`, it only tracks whether it is
+/// inside a string.
+pub fn minify(json: &str) -> String {
+    let mut res = String::with_capacity(json.len());
+    let mut in_string = false;
+    let mut escaped = false;
+    for c in json.chars() {
+        if in_string {
+            if escaped {
+                escaped = false;
+            } else if c == '\\' {
+                escaped = true;
+            } else if c == '"' {
+                in_string = false;
+            }
+        } else if matches!(c, ' ' | '\t' | '\n' | '\r') {
+            continue;
+        } else if c == '"' {
+            in_string = true;
+        }
+        res.push(c);
+    }
+    res
+}
+
+/// Returns the elements of the serialized array `json` without the outer
+/// brackets, for [`Array::extend_raw`](crate::Array::extend_raw), or `None`
+/// if `json` is not bracketed.
+pub fn array_elements(json: &str) -> Option<&str> {
+    let inner = json.trim().strip_prefix('[')?.strip_suffix(']')?;
+    Some(inner.trim())
+}
+
+/// Removes every `"key":null` field from the objects in `json`, along with
+/// its comma, for APIs which want absent fields rather than nulls.
+///
+/// Only fields are removed: `null` elements of arrays stay, and so do the
+/// objects left empty. Like [`minify`], this tracks strings and brackets
+/// rather than parsing `json`, which must be valid for the result to be.
+pub fn prune_nulls(json: &str) -> String {
+    struct Container {
+        is_object: bool,
+        /// Where the output of the object's first field goes.
+        open: usize,
+        /// Where the comma before the current field is in the output.
+        comma: Option<usize>,
+        kept: usize,
+        expect_key: bool,
+    }
+
+    let bytes = json.as_bytes();
+    let mut res = String::with_capacity(json.len());
+    let mut stack: Vec<Container> = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'"' => {
+                let end = string_end(bytes, i);
+                match stack.last_mut() {
+                    Some(obj) if obj.is_object && obj.expect_key => {
+                        obj.expect_key = false;
+                        if let Some(null_end) = null_value(bytes, end) {
+                            res.truncate(obj.comma.unwrap_or(obj.open));
+                            i = null_end;
+                            continue;
+                        }
+                        obj.kept += 1;
+                    }
+                    _ => (),
+                }
+                res.push_str(&json[i..end]);
+                i = end;
+                continue;
+            }
+            b',' => match stack.last_mut() {
+                Some(obj) if obj.is_object => {
+                    obj.expect_key = true;
+                    if obj.kept == 0 {
+                        obj.comma = None;
+                        i += 1;
+                        continue;
+                    }
+                    obj.comma = Some(res.len());
+                }
+                _ => (),
+            },
+            b @ (b'{' | b'[') => {
+                res.push(b as char);
+                stack.push(Container {
+                    is_object: b == b'{',
+                    open: res.len(),
+                    comma: None,
+                    kept: 0,
+                    expect_key: true,
+                });
+                i += 1;
+                continue;
+            }
+            b'}' | b']' => {
+                stack.pop();
+            }
+            _ => (),
+        }
+        let end = i + json[i..].chars().next().map_or(1, char::len_utf8);
+        res.push_str(&json[i..end]);
+        i = end;
+    }
+    return res;
+
+    /// The index past the string starting at `start`.
+    fn string_end(bytes: &[u8], start: usize) -> usize {
+        let mut i = start + 1;
+        while i < bytes.len() {
+            match bytes[i] {
+                b'\\' => i += 2,
+                b'"' => return i + 1,
+                _ => i += 1,
+            }
+        }
+        bytes.len()
+    }
+
+    /// If the key ending at `key_end` has a `null` value, the index past it.
+    fn null_value(bytes: &[u8], key_end: usize) -> Option<usize> {
+        let ws = |i: usize| {
+            i + bytes[i.min(bytes.len())..]
+                .iter()
+                .take_while(|b| b.is_ascii_whitespace())
+                .count()
+        };
+        let colon = ws(key_end);
+        if bytes.get(colon) != Some(&b':') {
+            return None;
+        }
+        let value = ws(colon + 1);
+        bytes[value..].starts_with(b"null").then_some(value + 4)
+    }
+}
+
+/// The number of `[` and `{` in `json` which are not closed, ignoring the
+/// ones in strings.
+pub(crate) fn unclosed(json: &str) -> isize {
+    let mut depth = 0;
+    let mut in_string = false;
+    let mut escaped = false;
+    for b in json.bytes() {
+        if in_string {
+            if escaped {
+                escaped = false;
+            } else if b == b'\\' {
+                escaped = true;
+            } else if b == b'"' {
+                in_string = false;
+            }
+            continue;
+        }
+        match b {
+            b'"' => in_string = true,
+            b'[' | b'{' => depth += 1,
+            b']' | b'}' => depth -= 1,
+            _ => (),
+        }
+    }
+    depth
+}
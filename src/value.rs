@@ -0,0 +1,155 @@
+use std::collections::HashMap;
+
+use crate::{encode_bool, encode_null, encode_number, encode_str, Array, Formatter, Object};
+
+/// A JSON value whose shape is only known at runtime.
+///
+/// Object fields are kept in order, and duplicate keys are written as is.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Value>),
+    Object(Vec<(String, Value)>),
+}
+
+impl Formatter {
+    /// Writes `value`, dispatching on its variant.
+    pub fn value(&self, buf: &mut String, value: &Value) {
+        match value {
+            Value::Null => encode_null(buf, self, ()),
+            Value::Bool(value) => encode_bool(buf, self, *value),
+            Value::Number(value) => encode_number(buf, self, *value),
+            Value::String(value) => encode_str(buf, self, value),
+            Value::Array(elements) => {
+                let mut arr = self.array(buf);
+                for element in elements {
+                    arr.value(element);
+                }
+            }
+            Value::Object(fields) => {
+                let mut obj = self.object(buf);
+                for (key, value) in fields {
+                    obj.value(key, value);
+                }
+            }
+        }
+    }
+}
+
+impl Formatter {
+    /// Writes `value` dictionary-encoded, as `{"strings":[...],"data":...}`:
+    /// every distinct string value is written once in `strings`, in order of
+    /// first appearance, and `data` is `value` with each string replaced by
+    /// `{"$":index}`. Keys are written as is.
+    ///
+    /// An object of `value` whose only key is `"$"` is written wrapped as
+    /// `{"$":{"$":...}}`, so a `"$"` holding a number is always a reference
+    /// and one holding an object is always that object.
+    pub fn value_dictionary(&self, buf: &mut String, value: &Value) {
+        let mut ids = HashMap::new();
+        let mut strings = Vec::new();
+        let data = intern(value, &mut ids, &mut strings);
+        let mut obj = self.object(buf);
+        let mut arr = obj.array("strings");
+        for string in strings {
+            arr.string(string);
+        }
+        drop(arr);
+        obj.value("data", &data);
+    }
+}
+
+fn intern<'a>(
+    value: &'a Value,
+    ids: &mut HashMap<&'a str, usize>,
+    strings: &mut Vec<&'a str>,
+) -> Value {
+    match value {
+        Value::String(string) => {
+            let id = *ids.entry(string).or_insert_with(|| {
+                strings.push(string);
+                strings.len() - 1
+            });
+            Value::Object(vec![("$".to_string(), Value::Number(id as f64))])
+        }
+        Value::Object(fields) if matches!(&fields[..], [(key, _)] if key == "$") => {
+            let inner = fields
+                .iter()
+                .map(|(key, value)| (key.clone(), intern(value, ids, strings)))
+                .collect();
+            Value::Object(vec![("$".to_string(), Value::Object(inner))])
+        }
+        Value::Array(elements) => Value::Array(
+            elements
+                .iter()
+                .map(|element| intern(element, ids, strings))
+                .collect(),
+        ),
+        Value::Object(fields) => Value::Object(
+            fields
+                .iter()
+                .map(|(key, value)| (key.clone(), intern(value, ids, strings)))
+                .collect(),
+        ),
+        scalar => scalar.clone(),
+    }
+}
+
+impl Object<'_> {
+    /// Writes a field with a runtime-typed `value`.
+    pub fn value<K: AsRef<str>>(&mut self, key: K, value: &Value) -> &mut Self {
+        match value {
+            Value::Null => self.null(key),
+            Value::Bool(value) => self.bool(key, *value),
+            Value::Number(value) => self.number(key, *value),
+            Value::String(value) => self.string(key, value),
+            Value::Array(elements) => {
+                let mut arr = self.array(key);
+                for element in elements {
+                    arr.value(element);
+                }
+                drop(arr);
+                self
+            }
+            Value::Object(fields) => {
+                let mut obj = self.object(key);
+                for (key, value) in fields {
+                    obj.value(key, value);
+                }
+                drop(obj);
+                self
+            }
+        }
+    }
+}
+
+impl Array<'_> {
+    /// Writes a runtime-typed element.
+    pub fn value(&mut self, value: &Value) -> &mut Self {
+        match value {
+            Value::Null => self.null(),
+            Value::Bool(value) => self.bool(*value),
+            Value::Number(value) => self.number(*value),
+            Value::String(value) => self.string(value),
+            Value::Array(elements) => {
+                let mut arr = self.array();
+                for element in elements {
+                    arr.value(element);
+                }
+                drop(arr);
+                self
+            }
+            Value::Object(fields) => {
+                let mut obj = self.object();
+                for (key, value) in fields {
+                    obj.value(key, value);
+                }
+                drop(obj);
+                self
+            }
+        }
+    }
+}
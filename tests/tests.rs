@@ -1,3 +1,7 @@
+mod validator;
+
+use validator::check;
+
 #[test]
 fn smoke() {
     let mut buf = String::new();
@@ -11,6 +15,7 @@ fn smoke() {
         obj.null("suitcase");
     }
 
+    check(&buf);
     assert_eq!(
         buf,
         r#"{"name":"Peter","favorite number":92,"films":["Drowning By Numbers","A Zed & Two Noughts"],"suitcase":null}"#
@@ -33,8 +38,10 @@ fn string_escaping() {
             .string("\x7F!")
             .string("Ċ");
     }
+    check(&buf);
     let strings = buf.replace(|c: char| "[],".contains(c), "\n");
-    let expected = r#"
+    let expected = concat!(
+        r#"
 ""
 "'"
 "\""
@@ -42,10 +49,2510 @@ fn string_escaping() {
 "hello world"
 " \r\n\t\\ \\r\\n\\t"
 "❤😂"
-"\u0000\u0007\u001F ~\u007F\u0080\u009F!"
-"\u007F!"
-"Ċ"
-"#;
+"#,
+        "\"\\u0000\\u0007\\u001F ~\u{7F}\u{80}\u{9F}!\"\n",
+        "\"\u{7F}!\"\n",
+        r#""Ċ"
+"#
+    );
 
     assert_eq!(strings, expected);
 }
+
+#[test]
+fn nested_chaining() {
+    let mut buf = String::new();
+    {
+        let mut obj = write_json::object(&mut buf);
+        obj.object("a").string("x", "1").string("y", "2");
+        obj.array("b").number(1.0).array().number(2.0).number(3.0);
+        {
+            let mut inner = obj.object("c");
+            inner.string("z", "3");
+            inner.object("d").bool("deep", true);
+        }
+        obj.null("e");
+    }
+    check(&buf);
+    assert_eq!(
+        buf,
+        r#"{"a":{"x":"1","y":"2"},"b":[1,[2,3]],"c":{"z":"3","d":{"deep":true}},"e":null}"#
+    );
+}
+
+#[test]
+fn try_number() {
+    let mut buf = String::new();
+    let err = write_json::try_number(&mut buf, f64::MAX * 2.0).unwrap_err();
+    assert!(matches!(err, write_json::Error::NonFinite(f64::INFINITY)));
+    assert!(write_json::try_number(&mut buf, f64::NAN).is_err());
+    assert_eq!(buf, "");
+
+    write_json::try_number(&mut buf, 1.5).unwrap();
+    check(&buf);
+    assert_eq!(buf, "1.5");
+
+    buf.clear();
+    {
+        let mut obj = write_json::object(&mut buf);
+        obj.try_number("a", 1.0).unwrap();
+        assert!(obj.try_number("b", f64::NEG_INFINITY).is_err());
+        let mut arr = obj.array("c");
+        assert!(arr.try_number(f64::NAN).is_err());
+        arr.try_number(2.0).unwrap();
+    }
+    check(&buf);
+    assert_eq!(buf, r#"{"a":1,"c":[2]}"#);
+}
+
+#[test]
+fn array_options() {
+    let mut buf = String::new();
+    {
+        let mut arr = write_json::array(&mut buf);
+        arr.options(vec![Some(1.0), None, Some(3.0), None], write_json::number);
+        arr.options([None, Some("x")], write_json::string);
+        arr.options(Vec::<Option<bool>>::new(), write_json::bool);
+    }
+    check(&buf);
+    assert_eq!(buf, r#"[1,null,3,null,null,"x"]"#);
+}
+
+#[test]
+fn key_prefix() {
+    let mut buf = String::new();
+    {
+        let mut obj = write_json::object(&mut buf);
+        obj.number("plain", 0.0);
+        obj.set_key_prefix("ns:").number("a", 1.0);
+        obj.object("reset").bool("b", true);
+        obj.object("inherit").set_key_prefix("ns:").bool("c", true);
+        obj.set_key_prefix("\"q\"").null("\n");
+        obj.set_key_prefix("").null("d");
+    }
+    check(&buf);
+    assert_eq!(
+        buf,
+        r#"{"plain":0,"ns:a":1,"ns:reset":{"b":true},"ns:inherit":{"ns:c":true},"\"q\"\n":null,"d":null}"#
+    );
+}
+
+#[test]
+fn try_string_bytes() {
+    let mut buf = String::new();
+    assert!(write_json::try_string_bytes(&mut buf, b"\xFF").is_err());
+    assert!(write_json::try_string_bytes(&mut buf, b"ok\xC3").is_err());
+    assert_eq!(buf, "");
+    write_json::try_string_bytes(&mut buf, "\"❤\"".as_bytes()).unwrap();
+    check(&buf);
+    assert_eq!(buf, r#""\"❤\"""#);
+
+    buf.clear();
+    {
+        let mut obj = write_json::object(&mut buf);
+        obj.try_string_bytes("a", b"x").unwrap();
+        assert!(obj.try_string_bytes("b", b"\xED\xA0\x80").is_err());
+        let mut arr = obj.array("c");
+        assert!(arr.try_string_bytes(b"\x80").is_err());
+        arr.try_string_bytes(b"y").unwrap();
+    }
+    check(&buf);
+    assert_eq!(buf, r#"{"a":"x","c":["y"]}"#);
+}
+
+#[test]
+fn string_bytes_unchecked() {
+    let inputs = ["", "plain", "\"quoted\"\n", "❤😂\u{7F}"];
+    for input in inputs {
+        let mut checked = String::new();
+        let mut unchecked = String::new();
+        write_json::try_string_bytes(&mut checked, input.as_bytes()).unwrap();
+        unsafe { write_json::string_bytes_unchecked(&mut unchecked, input.as_bytes()) };
+        check(&checked);
+        assert_eq!(checked, unchecked);
+    }
+
+    let mut buf = String::new();
+    {
+        let mut obj = write_json::object(&mut buf);
+        unsafe { obj.string_bytes_unchecked("a", b"x") };
+        let mut arr = obj.array("b");
+        unsafe { arr.string_bytes_unchecked("\\".as_bytes()) };
+    }
+    check(&buf);
+    assert_eq!(buf, r#"{"a":"x","b":["\\"]}"#);
+}
+
+#[test]
+fn sorted_object() {
+    let mut buf = String::new();
+    {
+        let mut obj = write_json::sorted_object(&mut buf);
+        obj.number("b", 2.0).string("a", "1");
+        obj.array("d").number(4.0);
+        obj.object("c").number("z", 0.0).number("y", 0.0);
+        obj.null("a");
+    }
+    check(&buf);
+    assert_eq!(buf, r#"{"a":"1","a":null,"b":2,"c":{"z":0,"y":0},"d":[4]}"#);
+
+    buf.clear();
+    {
+        let schema = ["id", "name"];
+        let rank = |key: &str| {
+            schema
+                .iter()
+                .position(|it| *it == key)
+                .unwrap_or(schema.len())
+        };
+        let mut obj = write_json::sorted_object_by(&mut buf, |l, r| {
+            rank(l).cmp(&rank(r)).then_with(|| l.cmp(r))
+        });
+        obj.string("zeta", "z")
+            .string("name", "n")
+            .string("alpha", "a")
+            .number("id", 1.0);
+    }
+    check(&buf);
+    assert_eq!(buf, r#"{"id":1,"name":"n","alpha":"a","zeta":"z"}"#);
+
+    buf.clear();
+    write_json::sorted_object(&mut buf);
+    check(&buf);
+    assert_eq!(buf, "{}");
+}
+
+#[test]
+fn streaming_object() {
+    fn write_fields(n: usize, mut field: impl FnMut(&str, f64)) {
+        for i in 0..n {
+            field(&format!("key {i}"), i as f64);
+        }
+    }
+
+    let mut expected = String::new();
+    {
+        let mut obj = write_json::object(&mut expected);
+        write_fields(1000, |key, value| {
+            obj.number(key, value);
+        });
+        obj.array("tail").string("\"end\"");
+    }
+
+    let mut out = String::new();
+    let mut flushes = 0;
+    let mut buf = String::new();
+    {
+        let mut obj = write_json::streaming_object(&mut buf, Some(64), |chunk| {
+            flushes += 1;
+            out.push_str(chunk);
+        });
+        write_fields(1000, |key, value| {
+            obj.number(key, value);
+        });
+        obj.array("tail").string("\"end\"");
+        obj.finish();
+    }
+    assert!(flushes > 100);
+    assert_eq!(buf, "");
+    check(&out);
+    assert_eq!(out, expected);
+}
+
+#[test]
+fn flush_threshold() {
+    fn flushes(threshold: Option<usize>, lower_to: Option<usize>) -> (usize, String) {
+        let mut buf = String::new();
+        let mut out = String::new();
+        let mut flushes = 0;
+        {
+            let mut obj = write_json::streaming_object(&mut buf, threshold, |chunk| {
+                flushes += 1;
+                out.push_str(chunk);
+            });
+            for i in 0..500 {
+                if i == 250 {
+                    if let Some(threshold) = lower_to {
+                        obj.set_flush_threshold(threshold);
+                    }
+                }
+                obj.number(format!("key {i}"), i as f64);
+            }
+        }
+        (flushes, out)
+    }
+
+    let (default, expected) = flushes(None, None);
+    let (small, out) = flushes(Some(256), None);
+    let (lowered, lowered_out) = flushes(None, Some(256));
+    check(&out);
+    assert_eq!(out, expected);
+    assert_eq!(lowered_out, expected);
+    assert!(
+        default < lowered && lowered < small,
+        "{default} {lowered} {small}"
+    );
+}
+
+#[test]
+fn put_aliases() {
+    let mut short = String::new();
+    {
+        let mut obj = write_json::object(&mut short);
+        obj.null("n")
+            .bool("b", true)
+            .number("x", 1.5)
+            .string("s", "v");
+        obj.object("o").null("n");
+        let mut arr = obj.array("a");
+        arr.null().bool(false).number(2.0).string("w");
+        arr.object().null("m");
+        arr.array().null();
+    }
+
+    let mut long = String::new();
+    {
+        let mut obj = write_json::object(&mut long);
+        obj.put_null("n")
+            .put_bool("b", true)
+            .put_number("x", 1.5)
+            .put_string("s", "v");
+        obj.put_object("o").put_null("n");
+        let mut arr = obj.put_array("a");
+        arr.put_null()
+            .put_bool(false)
+            .put_number(2.0)
+            .put_string("w");
+        arr.put_object().put_null("m");
+        arr.put_array().put_null();
+    }
+
+    assert_eq!(short, long);
+}
+
+#[test]
+fn validator() {
+    use validator::{validate, validate_lenient};
+
+    for ok in [
+        "null",
+        " [1, -0.5e+3, true, false, \"\\u00e9\\n\"] ",
+        r#"{"a":{"b":[[],{}]}}"#,
+    ] {
+        assert_eq!(validate(ok), Ok(()), "{ok}");
+    }
+    for bad in [
+        "",
+        "[1,2",
+        "[1,2]]",
+        "{\"a\":1",
+        "[1,]",
+        r#"{"a":1,}"#,
+        r#""\x""#,
+        r#""\u12G4""#,
+        "\"\n\"",
+        "01",
+        "1.",
+        "NaN",
+        "inf",
+        "{1:2}",
+    ] {
+        assert!(validate(bad).is_err(), "{bad}");
+    }
+    assert_eq!(validate_lenient("[1,]"), Ok(()));
+    assert_eq!(validate_lenient(r#"{"a":1,}"#), Ok(()));
+    assert!(validate_lenient("[1,,]").is_err());
+}
+
+#[test]
+fn jsonp_safe() {
+    let input = "<a>\u{2028}&\u{2029}";
+
+    let mut buf = String::new();
+    write_json::string(&mut buf, input);
+    assert_eq!(buf, "\"<a>\u{2028}&\u{2029}\"");
+
+    let fmt = write_json::Formatter::new().jsonp_safe(true);
+    buf.clear();
+    fmt.string(&mut buf, input);
+    assert_eq!(buf, r#""<a>\u2028&\u2029""#);
+
+    buf.clear();
+    {
+        let mut obj = fmt.object(&mut buf);
+        obj.array("\u{2028}").string("\u{2029}");
+    }
+    check(&buf);
+    assert_eq!(buf, r#"{"\u2028":["\u2029"]}"#);
+}
+
+#[test]
+fn resume_array() {
+    fn header(buf: &mut String) {
+        write_json::array(buf).suspend();
+    }
+    fn chunk(buf: &mut String, values: &[f64]) {
+        let mut arr = write_json::resume_array(buf);
+        for &value in values {
+            arr.number(value);
+        }
+        arr.suspend();
+    }
+    fn footer(buf: &mut String) {
+        write_json::resume_array(buf).string("end");
+    }
+
+    let mut buf = String::new();
+    header(&mut buf);
+    chunk(&mut buf, &[]);
+    chunk(&mut buf, &[1.0, 2.0]);
+    chunk(&mut buf, &[]);
+    chunk(&mut buf, &[3.0]);
+    footer(&mut buf);
+    check(&buf);
+    assert_eq!(buf, r#"[1,2,3,"end"]"#);
+
+    buf.clear();
+    header(&mut buf);
+    write_json::resume_array(&mut buf);
+    check(&buf);
+    assert_eq!(buf, "[]");
+
+    buf.clear();
+    let fmt = write_json::Formatter::new().max_entries(1);
+    let mut arr = fmt.array(&mut buf);
+    arr.number(1.0).number(2.0);
+    arr.suspend();
+    fmt.resume_array(&mut buf);
+    assert_eq!(buf, "[1]");
+}
+
+#[test]
+fn integral_numbers() {
+    let mut values = vec![
+        0.0,
+        -0.0,
+        1.0,
+        -1.0,
+        92.0,
+        1e15,
+        4503599627370497.0,
+        9007199254740991.0,
+        9007199254740992.0,
+        9007199254740994.0,
+        -9007199254740993.0,
+        9223372036854774784.0,
+        -9223372036854775808.0,
+        9223372036854775808.0,
+        1e20,
+        -1e300,
+        f64::MAX,
+        f64::MIN,
+        0.5,
+        -2.5,
+        f64::MIN_POSITIVE,
+    ];
+    let mut x = 1.0f64;
+    while x.is_finite() {
+        values.push(x);
+        values.push(-x - 1.0);
+        x *= 7.0;
+    }
+    for value in values {
+        let mut buf = String::new();
+        write_json::number(&mut buf, value);
+        assert_eq!(buf, format!("{value}"));
+    }
+}
+
+#[test]
+fn string_chars() {
+    let text = "say \"hi\"\n\t\\ ❤\x01";
+    let mut expected = String::new();
+    write_json::string(&mut expected, text);
+
+    let mut buf = String::new();
+    write_json::string_chars(&mut buf, text.chars());
+    assert_eq!(buf, expected);
+
+    buf.clear();
+    {
+        let mut obj = write_json::object(&mut buf);
+        obj.string_chars("upper", "a\"b".chars().map(|c| c.to_ascii_uppercase()));
+        obj.array("empty").string_chars(std::iter::empty());
+    }
+    check(&buf);
+    assert_eq!(buf, r#"{"upper":"A\"B","empty":[""]}"#);
+}
+
+#[test]
+fn object_custom() {
+    fn pair(buf: &mut String, (x, y): (f64, &str)) {
+        write_json::array(buf).number(x).string(y);
+    }
+
+    let mut buf = String::new();
+    {
+        let mut obj = write_json::object(&mut buf);
+        obj.custom("pair", (1.0, "one"), pair)
+            .custom("raw", "[true]", |buf, raw| buf.push_str(raw))
+            .null("after");
+    }
+    check(&buf);
+    assert_eq!(buf, r#"{"pair":[1,"one"],"raw":[true],"after":null}"#);
+}
+
+#[test]
+fn array_custom() {
+    let mut buf = String::new();
+    write_json::array(&mut buf)
+        .number(1.0)
+        .custom((2.0, 3.0), |buf, (x, y)| {
+            write_json::object(buf).number("x", x).number("y", y);
+        })
+        .custom("raw", write_json::string)
+        .number(4.0);
+    check(&buf);
+    assert_eq!(buf, r#"[1,{"x":2,"y":3},"raw",4]"#);
+}
+
+#[test]
+fn lowercase_hex() {
+    let input = "\x1F\x0B\n";
+
+    let mut buf = String::new();
+    write_json::string(&mut buf, input);
+    assert_eq!(buf, r#""\u001F\u000B\n""#);
+
+    buf.clear();
+    let fmt = write_json::Formatter::new().lowercase_hex(true);
+    fmt.array(&mut buf).string(input);
+    check(&buf);
+    assert_eq!(buf, r#"["\u001f\u000b\n"]"#);
+}
+
+#[test]
+fn minify() {
+    let pretty = r#"
+{
+    "name": "  spaced  out  ",
+    "escaped": "\" \\",
+    "list": [
+        1,
+        { "a\tb": "\n" }
+    ]
+}
+"#;
+    let minified = write_json::minify(pretty);
+    check(&minified);
+    assert_eq!(
+        minified,
+        r#"{"name":"  spaced  out  ","escaped":"\" \\","list":[1,{"a\tb":"\n"}]}"#
+    );
+}
+
+#[test]
+fn hashing_object() {
+    use std::hash::Hasher;
+
+    fn write_doc<H: Hasher>(buf: &mut String, hasher: H) -> u64 {
+        let mut obj = write_json::hashing_object(buf, hasher);
+        obj.string("name", "\"quoted\"").number("n", 1.5);
+        obj.array("list").null().bool(true);
+        obj.object("nested").string("k", "v");
+        obj.bool("last", false);
+        obj.finish()
+    }
+
+    let mut buf = String::new();
+    let digest = write_doc(&mut buf, write_json::Fnv1a::default());
+    check(&buf);
+    let mut expected = write_json::Fnv1a::default();
+    expected.write(buf.as_bytes());
+    assert_eq!(digest, expected.finish());
+
+    let mut buf = String::from("prefix ");
+    let digest = write_doc(&mut buf, write_json::Crc32::default());
+    let mut expected = write_json::Crc32::default();
+    expected.write(&buf.as_bytes()["prefix ".len()..]);
+    assert_eq!(digest, expected.finish());
+}
+
+#[test]
+fn array_of_object_of() {
+    let mut buf = String::new();
+    write_json::object(&mut buf)
+        .string("before", "a")
+        .array_of("nums", 0..3, |buf, i| write_json::number(buf, i as f64))
+        .object_of("map", [("x", true), ("y", false)], write_json::bool)
+        .array_of("empty", Vec::<&str>::new(), write_json::string)
+        .object_of("owned", vec![(String::from("k"), "v")], write_json::string)
+        .string("after", "b");
+    check(&buf);
+    assert_eq!(
+        buf,
+        r#"{"before":"a","nums":[0,1,2],"map":{"x":true,"y":false},"empty":[],"owned":{"k":"v"},"after":"b"}"#
+    );
+}
+
+#[test]
+fn always_fraction() {
+    let values = [1.0, -0.0, 1.5, 1e20, -3.0];
+
+    let mut buf = String::new();
+    write_json::array(&mut buf).options(values.map(Some), write_json::number);
+    assert_eq!(buf, "[1,-0,1.5,100000000000000000000,-3]");
+
+    buf.clear();
+    let fmt = write_json::Formatter::new().always_fraction(true);
+    {
+        let mut arr = fmt.array(&mut buf);
+        for value in values {
+            arr.number(value);
+        }
+        arr.object().number("nested", 2.0);
+    }
+    check(&buf);
+    assert_eq!(
+        buf,
+        r#"[1.0,-0.0,1.5,100000000000000000000.0,-3.0,{"nested":2.0}]"#
+    );
+
+    buf.clear();
+    fmt.number(&mut buf, 7.0);
+    assert_eq!(buf, "7.0");
+}
+
+#[test]
+fn array_of_objects() {
+    struct Row {
+        id: f64,
+        name: &'static str,
+    }
+
+    let mut buf = String::new();
+    write_json::array_of_objects(&mut buf, Vec::<Row>::new(), |_, _| unreachable!());
+    check(&buf);
+    assert_eq!(buf, "[]");
+
+    buf.clear();
+    let rows = [Row { id: 1.0, name: "a" }, Row { id: 2.0, name: "b" }];
+    write_json::array_of_objects(&mut buf, &rows, |obj, row| {
+        obj.number("id", row.id).string("name", row.name);
+    });
+    check(&buf);
+    assert_eq!(buf, r#"[{"id":1,"name":"a"},{"id":2,"name":"b"}]"#);
+}
+
+#[test]
+fn needs_escaping() {
+    for clean in [
+        "",
+        "hello world",
+        "'/<>&",
+        "❤😂",
+        "\u{A0}é",
+        "\u{2028}",
+        "\x7F",
+    ] {
+        assert!(!write_json::needs_escaping(clean), "{clean:?}");
+    }
+    for dirty in ["\"", "a\\b", "\n", "\x00"] {
+        assert!(write_json::needs_escaping(dirty), "{dirty:?}");
+    }
+
+    let c1 = write_json::Formatter::new().escape_c1(true);
+    assert!(c1.needs_escaping("\x7F"));
+    assert!(c1.needs_escaping("❤\u{85}"));
+    assert!(!c1.needs_escaping("\u{A0}"));
+
+    let jsonp = write_json::Formatter::new().jsonp_safe(true);
+    assert!(jsonp.needs_escaping("a\u{2028}"));
+    assert!(jsonp.needs_escaping("\u{2029}"));
+    assert!(!jsonp.needs_escaping("❤<"));
+
+    for s in ["plain", "\"", "\u{2028}", "é\u{9F}", "\x7F"] {
+        for fmt in [write_json::Formatter::new(), jsonp, c1] {
+            let mut buf = String::new();
+            fmt.string(&mut buf, s);
+            assert_eq!(fmt.needs_escaping(s), buf != format!("\"{s}\""));
+        }
+    }
+}
+
+#[test]
+fn ranges() {
+    let mut buf = String::new();
+    {
+        let mut obj = write_json::object(&mut buf);
+        obj.range("half_open", 0..10);
+        obj.array("list").range(-5..-5).range(i64::MIN..i64::MAX);
+    }
+    check(&buf);
+    assert_eq!(
+        buf,
+        r#"{"half_open":[0,10],"list":[[-5,-5],[-9223372036854775808,9223372036854775807]]}"#
+    );
+}
+
+#[test]
+fn placeholder() {
+    let mut buf = String::new();
+    let (len, count);
+    {
+        let mut obj = write_json::object(&mut buf);
+        len = obj.placeholder("len", 6);
+        let mut arr = obj.array("items");
+        arr.string("a").string("b");
+        count = arr.placeholder(2);
+    }
+    check(&buf);
+    assert_eq!(buf, r#"{"len":     0,"items":["a","b", 0]}"#);
+
+    let total = buf.len() as u64;
+    len.fill(&mut buf, total);
+    count.fill(&mut buf, 3);
+    check(&buf);
+    assert_eq!(buf, r#"{"len":    35,"items":["a","b", 3]}"#);
+}
+
+#[test]
+fn placeholder_left_out() {
+    let mut buf = String::new();
+    let (secret, len, extra);
+    {
+        let fmt = write_json::Formatter::new()
+            .skip_fields(|key| key == "secret")
+            .max_entries(2);
+        let mut obj = fmt.object(&mut buf);
+        secret = obj.placeholder("secret", 3);
+        len = obj.placeholder("len", 3);
+        extra = obj.array("items").number(1.0).number(2.0).placeholder(3);
+        obj.string("name", "Peter");
+    }
+    secret.fill(&mut buf, 1);
+    len.fill(&mut buf, 2);
+    extra.fill(&mut buf, 3);
+    check(&buf);
+    assert_eq!(buf, r#"{"len":  2,"items":[1,2],"_truncated":true}"#);
+}
+
+#[test]
+#[should_panic = "does not fit"]
+fn placeholder_overflow() {
+    let mut buf = String::new();
+    let placeholder = write_json::array(&mut buf).placeholder(2);
+    placeholder.fill(&mut buf, 100);
+}
+
+#[test]
+fn errors() {
+    use std::error::Error as _;
+    use write_json::Error;
+
+    let bytes = vec![0xFF];
+    let utf8 = std::str::from_utf8(&bytes).unwrap_err();
+    let cases = [
+        (
+            Error::from(std::io::Error::other("disk full")),
+            "failed to write JSON: disk full",
+        ),
+        (
+            Error::NonFinite(f64::NAN),
+            "non-finite number cannot be represented in JSON: NaN",
+        ),
+        (
+            Error::from(utf8),
+            "string is not valid UTF-8: invalid utf-8 sequence of 1 bytes from index 0",
+        ),
+        (Error::Overflow, "output buffer is full"),
+    ];
+    for (err, message) in cases {
+        assert_eq!(err.to_string(), message);
+        let has_source = matches!(err, Error::Io(_) | Error::InvalidUtf8(_));
+        assert_eq!(err.source().is_some(), has_source);
+    }
+
+    let mut buf = String::new();
+    let err = write_json::try_string_bytes(&mut buf, b"\xFF").unwrap_err();
+    assert!(matches!(err, Error::InvalidUtf8(_)));
+}
+
+#[test]
+fn pretty() {
+    let fmt = write_json::Formatter::new().pretty(2);
+    let mut buf = String::new();
+    {
+        let mut obj = fmt.object(&mut buf);
+        obj.string("name", "Peter").number("favorite number", 92.0);
+        obj.array("films")
+            .string("Drowning By Numbers")
+            .object()
+            .bool("nested", true);
+        obj.object("empty");
+        obj.array("none");
+    }
+    check(&buf);
+    assert_eq!(
+        buf,
+        r#"{
+  "name": "Peter",
+  "favorite number": 92,
+  "films": [
+    "Drowning By Numbers",
+    {
+      "nested": true
+    }
+  ],
+  "empty": {},
+  "none": []
+}"#
+    );
+}
+
+#[test]
+fn pretty_elements_per_line() {
+    let fmt = write_json::Formatter::new().pretty(2).elements_per_line(4);
+    let mut buf = String::new();
+    {
+        let mut obj = fmt.object(&mut buf);
+        let mut arr = obj.array("numbers");
+        for i in 1..=10 {
+            arr.number(i as f64);
+        }
+    }
+    check(&buf);
+    assert_eq!(
+        buf,
+        r#"{
+  "numbers": [
+    1, 2, 3, 4,
+    5, 6, 7, 8,
+    9, 10
+  ]
+}"#
+    );
+
+    buf.clear();
+    write_json::Formatter::new()
+        .elements_per_line(4)
+        .array(&mut buf)
+        .number(1.0)
+        .number(2.0);
+    assert_eq!(buf, "[1,2]");
+}
+
+#[test]
+fn sorted_array() {
+    let mut buf = String::new();
+    {
+        let mut arr = write_json::sorted_array(&mut buf);
+        arr.string("pear")
+            .string("apple")
+            .string("fig")
+            .string("apple");
+    }
+    check(&buf);
+    assert_eq!(buf, r#"["apple","apple","fig","pear"]"#);
+
+    buf.clear();
+    {
+        let mut arr = write_json::sorted_array(&mut buf);
+        arr.number(10.0).null().bool(true);
+        arr.array().number(2.0);
+        arr.object().string("k", "v");
+        arr.string("s").number(9.0);
+    }
+    check(&buf);
+    assert_eq!(buf, r#"["s",10,9,[2],null,true,{"k":"v"}]"#);
+}
+
+#[test]
+fn shared_keys() {
+    use std::{borrow::Cow, rc::Rc, sync::Arc};
+
+    let arc: Arc<str> = Arc::from("arc");
+    let rc: Rc<str> = Rc::from("rc");
+    let cow: Cow<str> = Cow::Owned(String::from("cow"));
+    let owned = String::from("owned");
+
+    let mut buf = String::new();
+    {
+        let mut obj = write_json::object(&mut buf);
+        obj.number(&arc, 1.0).number(arc.clone(), 2.0);
+        obj.bool(&rc, true);
+        obj.string(&cow, "x").null(Cow::Borrowed("borrowed"));
+        obj.array(&owned).number(3.0);
+        obj.object(owned).null("\"");
+    }
+    check(&buf);
+    assert_eq!(
+        buf,
+        r#"{"arc":1,"arc":2,"rc":true,"cow":"x","borrowed":null,"owned":[3],"owned":{"\"":null}}"#
+    );
+
+    buf.clear();
+    write_json::sorted_object(&mut buf)
+        .number(arc, 1.0)
+        .number(Cow::Borrowed("a"), 0.0);
+    assert_eq!(buf, r#"{"a":0,"arc":1}"#);
+}
+
+#[test]
+fn escape_c1() {
+    let input = "\x7E\x7F\u{80}\u{9F}\u{A0}";
+
+    // This is what `serde_json::to_string` produces: only `"`, `\` and
+    // characters below U+0020 are escaped.
+    let mut buf = String::new();
+    write_json::string(&mut buf, input);
+    assert_eq!(buf, "\"~\u{7F}\u{80}\u{9F}\u{A0}\"");
+    buf.clear();
+    write_json::string(&mut buf, "\x00\x1F");
+    assert_eq!(buf, r#""\u0000\u001F""#);
+
+    buf.clear();
+    let fmt = write_json::Formatter::new().escape_c1(true);
+    fmt.array(&mut buf).string(input);
+    check(&buf);
+    assert_eq!(buf, "[\"~\\u007F\\u0080\\u009F\u{A0}\"]");
+}
+
+#[test]
+fn bytes_as_numbers() {
+    let mut buf = String::new();
+    {
+        let mut obj = write_json::object(&mut buf);
+        obj.bytes_as_numbers("dead", &[0xDE, 0xAD]);
+        obj.array("list")
+            .bytes_as_numbers(&[])
+            .bytes_as_numbers(&[0, 9, 10, 99, 100, 255]);
+    }
+    check(&buf);
+    assert_eq!(buf, r#"{"dead":[222,173],"list":[[],[0,9,10,99,100,255]]}"#);
+}
+
+#[test]
+fn display_keys() {
+    use std::fmt;
+
+    #[derive(Clone, Copy)]
+    enum Color {
+        Red,
+        Green,
+        Quoted,
+    }
+
+    impl fmt::Display for Color {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str(match self {
+                Color::Red => "red",
+                Color::Green => "green",
+                Color::Quoted => "\"blue\"",
+            })
+        }
+    }
+
+    let mut buf = String::new();
+    {
+        let mut obj = write_json::object(&mut buf);
+        obj.number("first", 0.0);
+        obj.entries(
+            [(Color::Red, 1.0), (Color::Green, 2.0), (Color::Quoted, 3.0)],
+            write_json::number,
+        );
+        obj.set_key_prefix("c:")
+            .entries([(Color::Red, true)], write_json::bool)
+            .entries([(42, "answer")], write_json::string);
+    }
+    check(&buf);
+    assert_eq!(
+        buf,
+        r#"{"first":0,"red":1,"green":2,"\"blue\"":3,"c:red":true,"c:42":"answer"}"#
+    );
+}
+
+#[test]
+fn write_value() {
+    use write_json::Value;
+
+    let scalars = [
+        (Value::Null, "null"),
+        (Value::Bool(true), "true"),
+        (Value::Number(-1.5), "-1.5"),
+        (Value::String("\"hi\"".to_string()), r#""\"hi\"""#),
+        (Value::Array(vec![]), "[]"),
+        (Value::Object(vec![]), "{}"),
+    ];
+    for (value, expected) in &scalars {
+        let mut buf = String::new();
+        write_json::write_value(&mut buf, value);
+        check(&buf);
+        assert_eq!(buf, *expected);
+    }
+
+    let doc = Value::Object(vec![
+        (
+            "all".to_string(),
+            Value::Array(scalars.map(|(value, _)| value).to_vec()),
+        ),
+        (
+            "nested".to_string(),
+            Value::Object(vec![("deep".to_string(), Value::Array(vec![Value::Null]))]),
+        ),
+    ]);
+    let mut buf = String::new();
+    write_json::write_value(&mut buf, &doc);
+    check(&buf);
+    assert_eq!(
+        buf,
+        r#"{"all":[null,true,-1.5,"\"hi\"",[],{}],"nested":{"deep":[null]}}"#
+    );
+
+    buf.clear();
+    {
+        let mut obj = write_json::object(&mut buf);
+        obj.value("doc", &doc);
+        obj.array("list").value(&Value::Bool(false));
+    }
+    check(&buf);
+
+    buf.clear();
+    write_json::Formatter::new().pretty(1).value(
+        &mut buf,
+        &Value::Array(vec![Value::Array(vec![Value::Null])]),
+    );
+    assert_eq!(buf, "[\n [\n  null\n ]\n]");
+}
+
+#[test]
+fn insertion_order() {
+    let keys = ["zulu", "yankee", "xray", "mike", "delta", "alpha"];
+
+    let mut buf = String::new();
+    {
+        let mut obj = write_json::object(&mut buf);
+        for (i, key) in keys.iter().enumerate() {
+            obj.number(key, i as f64);
+        }
+    }
+    check(&buf);
+    assert_eq!(
+        buf,
+        r#"{"zulu":0,"yankee":1,"xray":2,"mike":3,"delta":4,"alpha":5}"#
+    );
+
+    buf.clear();
+    {
+        let mut obj = write_json::sorted_object(&mut buf);
+        for (i, key) in keys.iter().enumerate() {
+            obj.number(key, i as f64);
+        }
+    }
+    check(&buf);
+    assert_eq!(
+        buf,
+        r#"{"alpha":5,"delta":4,"mike":3,"xray":2,"yankee":1,"zulu":0}"#
+    );
+}
+
+#[test]
+fn extend_raw() {
+    let mut other = String::new();
+    write_json::array(&mut other).number(2.0).string("three");
+    let elements = write_json::array_elements(&other).unwrap();
+    assert_eq!(elements, r#"2,"three""#);
+
+    let mut buf = String::new();
+    {
+        let mut arr = write_json::array(&mut buf);
+        arr.number(1.0).extend_raw(elements).null();
+        arr.extend_raw(write_json::array_elements(" [ ] ").unwrap());
+    }
+    check(&buf);
+    assert_eq!(buf, r#"[1,2,"three",null]"#);
+
+    buf.clear();
+    write_json::array(&mut buf)
+        .extend_raw("")
+        .extend_raw(elements);
+    check(&buf);
+    assert_eq!(buf, r#"[2,"three"]"#);
+
+    assert_eq!(write_json::array_elements(r#"{"a":1}"#), None);
+}
+
+#[test]
+fn non_finite_tokens() {
+    let fmt = write_json::Formatter::new().non_finite_tokens(true);
+    let mut buf = String::new();
+    {
+        let mut arr = fmt.array(&mut buf);
+        arr.number(f64::NAN)
+            .number(f64::INFINITY)
+            .number(f64::NEG_INFINITY)
+            .number(1.5);
+    }
+    assert_eq!(buf, "[NaN,Infinity,-Infinity,1.5]");
+
+    buf.clear();
+    fmt.always_fraction(true).number(&mut buf, f64::INFINITY);
+    assert_eq!(buf, "Infinity");
+}
+
+#[test]
+fn float_format() {
+    use std::fmt::Write;
+
+    let fmt = write_json::Formatter::new().float_format(|buf, value| {
+        let _ = write!(buf, "{value:.3}");
+    });
+    let mut buf = String::new();
+    {
+        let mut obj = fmt.object(&mut buf);
+        obj.number("pi", std::f64::consts::PI).number("one", 1.0);
+        obj.array("nested").number(-0.0005).number(2.5);
+    }
+    check(&buf);
+    assert_eq!(buf, r#"{"pi":3.142,"one":1.000,"nested":[-0.001,2.500]}"#);
+}
+
+#[test]
+fn value_dictionary() {
+    use write_json::Value;
+
+    let event = |kind: &str, user: &str| {
+        Value::Object(vec![
+            ("kind".to_string(), Value::String(kind.to_string())),
+            ("user".to_string(), Value::String(user.to_string())),
+            ("ok".to_string(), Value::Bool(true)),
+        ])
+    };
+    let doc = Value::Array(vec![
+        event("login", "ann"),
+        event("view", "ann"),
+        event("login", "bob"),
+    ]);
+    let mut buf = String::new();
+    write_json::write_value_dictionary(&mut buf, &doc);
+    check(&buf);
+    assert_eq!(
+        buf,
+        concat!(
+            r#"{"strings":["login","ann","view","bob"],"data":["#,
+            r#"{"kind":{"$":0},"user":{"$":1},"ok":true},"#,
+            r#"{"kind":{"$":2},"user":{"$":1},"ok":true},"#,
+            r#"{"kind":{"$":0},"user":{"$":3},"ok":true}]}"#,
+        )
+    );
+
+    let doc = Value::Array(vec![
+        Value::Number(0.0),
+        Value::Object(vec![("$".to_string(), Value::Number(0.0))]),
+        Value::String("x".to_string()),
+    ]);
+    buf.clear();
+    write_json::write_value_dictionary(&mut buf, &doc);
+    assert_eq!(buf, r#"{"strings":["x"],"data":[0,{"$":{"$":0}},{"$":0}]}"#);
+}
+
+#[test]
+fn escape_into_slice() {
+    let input = "a\"\n\u{1}é";
+    let escaped = r#"a\"\n\u0001é"#;
+
+    let mut dst = [0; 13];
+    assert_eq!(escaped.len(), dst.len());
+    let len = write_json::escape_into_slice(&mut dst, input).unwrap();
+    assert_eq!(std::str::from_utf8(&dst[..len]).unwrap(), escaped);
+
+    let mut dst = [0; 12];
+    let err = write_json::escape_into_slice(&mut dst, input).unwrap_err();
+    assert!(matches!(err, write_json::Error::Overflow));
+
+    let mut dst = [0; 3];
+    assert_eq!(write_json::escape_into_slice(&mut dst, "abc").unwrap(), 3);
+    assert!(write_json::escape_into_slice(&mut dst, "abcd").is_err());
+    assert_eq!(write_json::escape_into_slice(&mut [], "").unwrap(), 0);
+}
+
+#[test]
+fn escaping_backends_agree() {
+    let formatters = [
+        write_json::Formatter::new(),
+        write_json::Formatter::new()
+            .jsonp_safe(true)
+            .escape_c1(true)
+            .lowercase_hex(true),
+        write_json::Formatter::new().strip_controls(true),
+    ];
+    let inputs = [
+        "plain",
+        "a\"\n\t\u{1}\u{1F}",
+        "\u{7F}\u{9F}é\u{2028}\u{2029}😀",
+        "",
+    ];
+    for fmt in formatters {
+        for input in inputs {
+            let mut string = String::new();
+            fmt.string(&mut string, input);
+            let mut chars = String::new();
+            fmt.string_chars(&mut chars, input.chars());
+            let mut dst = [0; 64];
+            let len = fmt.escape_into_slice(&mut dst, input).unwrap();
+            let slice = std::str::from_utf8(&dst[..len]).unwrap();
+            assert_eq!(string, chars);
+            assert_eq!(string, format!("\"{slice}\""));
+        }
+    }
+}
+
+#[test]
+#[cfg_attr(debug_assertions, should_panic(expected = "empty object key"))]
+fn deny_empty_keys() {
+    let fmt = write_json::Formatter::new().deny_empty_keys(true);
+    let mut buf = String::new();
+    let mut obj = fmt.object(&mut buf);
+    obj.number("a", 1.0);
+    obj.set_key_prefix("p");
+    obj.number("", 2.0);
+    obj.set_key_prefix("");
+    obj.number("", 3.0);
+}
+
+#[test]
+fn allow_empty_keys() {
+    let mut buf = String::new();
+    write_json::object(&mut buf).number("", 1.0);
+    assert_eq!(buf, r#"{"":1}"#);
+}
+
+#[test]
+fn pairs() {
+    let enc = |buf: &mut String, value: &f64| write_json::number(buf, *value);
+    let mut buf = String::new();
+    write_json::pairs(&mut buf, &[("a", 1.0), ("b", 2.0)], enc);
+    check(&buf);
+    assert_eq!(buf, r#"{"a":1,"b":2}"#);
+
+    buf.clear();
+    write_json::pairs(&mut buf, &[("a", 1.0), ("b", 2.0), ("a", 3.0)], enc);
+    check(&buf);
+    assert_eq!(buf, r#"[["a",1],["b",2],["a",3]]"#);
+
+    buf.clear();
+    {
+        let tags = [(String::from("x"), 0.0), (String::from("x"), 1.0)];
+        let mut obj = write_json::object(&mut buf);
+        obj.pairs("unique", &[("y", 0.0)], enc)
+            .pairs("duplicate", &tags, enc)
+            .pairs("empty", &[] as &[(&str, f64)], enc);
+    }
+    check(&buf);
+    assert_eq!(
+        buf,
+        r#"{"unique":{"y":0},"duplicate":[["x",0],["x",1]],"empty":{}}"#
+    );
+}
+
+#[test]
+fn reuse_buffer() {
+    let mut pool = vec![String::with_capacity(1024)];
+    for i in 0..3 {
+        let mut buf = pool.pop().unwrap();
+        buf.clear();
+        write_json::object_reuse(&mut buf).number("request", i as f64);
+        assert_eq!(buf, format!(r#"{{"request":{i}}}"#));
+        assert!(buf.capacity() >= 1024);
+        pool.push(buf);
+    }
+
+    let mut buf = pool.pop().unwrap();
+    buf.clear();
+    write_json::array_reuse(&mut buf).null();
+    assert_eq!(buf, "[null]");
+}
+
+#[test]
+#[cfg_attr(
+    debug_assertions,
+    should_panic(expected = "reused buffer is not empty")
+)]
+fn reuse_non_empty_buffer() {
+    let mut buf = String::from("stale");
+    write_json::array_reuse(&mut buf).null();
+}
+
+#[test]
+fn max_entries() {
+    let fmt = write_json::Formatter::new().max_entries(2);
+    let mut buf = String::new();
+    {
+        let mut obj = fmt.object(&mut buf);
+        obj.number("a", 1.0);
+        obj.array("b").number(1.0).number(2.0).number(3.0);
+        obj.set_key_prefix("x_");
+        obj.object("c").null("deep");
+        obj.string("d", "gone");
+    }
+    check(&buf);
+    assert_eq!(buf, r#"{"a":1,"b":[1,2],"_truncated":true}"#);
+
+    buf.clear();
+    fmt.object(&mut buf).number("a", 1.0).number("b", 2.0);
+    assert_eq!(buf, r#"{"a":1,"b":2}"#);
+
+    buf.clear();
+    let fmt = write_json::Formatter::new().max_entries(0).pretty(2);
+    {
+        let mut obj = fmt.object(&mut buf);
+        obj.array("a").null();
+        obj.null("b");
+    }
+    check(&buf);
+    assert_eq!(buf, "{\n  \"_truncated\": true\n}");
+}
+
+#[test]
+fn chunked_array() {
+    let mut chunks = Vec::new();
+    let mut arr = write_json::chunked_array();
+    arr.write(|arr| {
+        arr.number(1.0);
+    });
+    chunks.push(arr.take_chunk());
+    chunks.push(arr.take_chunk());
+    arr.write(|arr| {
+        arr.object().string("name", "a\"b");
+        arr.array().null();
+    });
+    chunks.push(arr.take_chunk());
+    arr.write(|arr| {
+        arr.bool(true);
+    });
+    chunks.push(arr.finish());
+    assert_eq!(chunks, ["[1", "", r#",{"name":"a\"b"},[null]"#, ",true]"]);
+
+    let doc = chunks.concat();
+    check(&doc);
+
+    let empty = write_json::chunked_array().finish();
+    assert_eq!(empty, "[]");
+}
+
+#[test]
+fn rename_keys() {
+    assert_eq!(write_json::camel_case("user_id"), "userId");
+    assert_eq!(write_json::camel_case("_private_key_"), "_privateKey");
+    assert_eq!(write_json::camel_case("plain"), "plain");
+    assert_eq!(write_json::snake_case("userId"), "user_id");
+    assert_eq!(write_json::snake_case("UserID"), "user_i_d");
+
+    let fmt = write_json::Formatter::new().rename_keys(write_json::camel_case);
+    let mut buf = String::new();
+    {
+        let mut obj = fmt.object(&mut buf);
+        obj.number("user_id", 1.0).string("say_\"hi\"", "x");
+        obj.object("nested_object").bool("is_ok", true);
+        obj.set_key_prefix("x_");
+        obj.null("file_name");
+    }
+    check(&buf);
+    assert_eq!(
+        buf,
+        r#"{"userId":1,"say\"hi\"":"x","nestedObject":{"isOk":true},"x_fileName":null}"#
+    );
+
+    buf.clear();
+    let fmt = write_json::Formatter::new().rename_keys(write_json::snake_case);
+    fmt.object(&mut buf).number("userId", 1.0);
+    assert_eq!(buf, r#"{"user_id":1}"#);
+}
+
+#[test]
+fn number_fixed() {
+    let mut buf = String::new();
+    {
+        let mut obj = write_json::object(&mut buf);
+        obj.number("ratio", 1.0 / 3.0).number_fixed("price", 9.9, 2);
+        obj.array("prices")
+            .number_fixed(0.125, 2)
+            .number_fixed(7.0, 0);
+    }
+    check(&buf);
+    assert_eq!(
+        buf,
+        r#"{"ratio":0.3333333333333333,"price":9.90,"prices":[0.12,7]}"#
+    );
+}
+
+#[test]
+fn run_length() {
+    let mut buf = String::new();
+    write_json::run_length(&mut buf, &[0.0, 0.0, 0.0, 5.0, 5.0]);
+    check(&buf);
+    assert_eq!(buf, "[[0,3],[5,2]]");
+
+    buf.clear();
+    {
+        let mut obj = write_json::object(&mut buf);
+        obj.run_length("empty", &[]);
+        obj.array("signed")
+            .run_length(&[0.0, -0.0, -0.0, 1.5])
+            .run_length(&[7.0]);
+    }
+    check(&buf);
+    assert_eq!(
+        buf,
+        r#"{"empty":[],"signed":[[[0,1],[-0,2],[1.5,1]],[[7,1]]]}"#
+    );
+}
+
+#[test]
+fn aliases() {
+    let mut buf = String::new();
+    {
+        let mut obj = write_json::object(&mut buf);
+        obj.aliases(&["id", "userId", "user_id"], 42.0, write_json::number)
+            .aliases(&["only"], "x", write_json::string)
+            .aliases(&[] as &[&str], (), |_, ()| unreachable!());
+    }
+    check(&buf);
+    assert_eq!(buf, r#"{"id":42,"userId":42,"user_id":42,"only":"x"}"#);
+}
+
+#[test]
+fn schema_object() {
+    let schema = ["id", "name", "tags"];
+    let mut buf = String::new();
+    {
+        let mut obj = write_json::schema_object(&mut buf, &schema);
+        obj.bool("extra", true);
+        obj.array("tags").string("a");
+        obj.string("name", "Peter")
+            .null("also_extra")
+            .number("id", 1.0);
+    }
+    check(&buf);
+    assert_eq!(
+        buf,
+        r#"{"id":1,"name":"Peter","tags":["a"],"extra":true,"also_extra":null}"#
+    );
+}
+
+#[test]
+fn prepared_key() {
+    use write_json::PreparedKey;
+
+    let id = PreparedKey::new("id");
+    let label = PreparedKey::new("la\"bel");
+    let mut buf = String::new();
+    {
+        let mut arr = write_json::array(&mut buf);
+        for i in 0..3 {
+            arr.object()
+                .field_prepared(&id, i as f64, write_json::number)
+                .field_prepared(&label, "x", write_json::string)
+                .null("plain");
+        }
+    }
+    check(&buf);
+    assert_eq!(
+        buf,
+        r#"[{"id":0,"la\"bel":"x","plain":null},{"id":1,"la\"bel":"x","plain":null},{"id":2,"la\"bel":"x","plain":null}]"#
+    );
+
+    let fmt = write_json::Formatter::new()
+        .pretty(2)
+        .rename_keys(write_json::camel_case);
+    let key = fmt.prepare_key("user_id");
+    buf.clear();
+    fmt.object(&mut buf)
+        .field_prepared(&key, 1.0, write_json::number);
+    assert_eq!(buf, "{\n  \"userId\": 1\n}");
+}
+
+#[test]
+fn number_str_relaxed() {
+    let mut buf = String::new();
+    write_json::number_str_relaxed(&mut buf, "1_000").unwrap();
+    assert_eq!(buf, "1000");
+
+    buf.clear();
+    {
+        let mut obj = write_json::object(&mut buf);
+        obj.number_str_relaxed("big", "-1_000_000.000_1e1_0")
+            .unwrap();
+        obj.array("list")
+            .number_str_relaxed("0")
+            .unwrap()
+            .number_str_relaxed("2.5E-3")
+            .unwrap();
+        for bad in [
+            "1__0", "1_", "_1", "1._5", "01", "1.", "+1", "0x10", "", "1e",
+        ] {
+            let res = obj.number_str_relaxed("bad", bad);
+            assert!(
+                matches!(res, Err(write_json::Error::InvalidNumber(_))),
+                "{bad}"
+            );
+        }
+    }
+    check(&buf);
+    assert_eq!(buf, r#"{"big":-1000000.0001e10,"list":[0,2.5E-3]}"#);
+}
+
+#[test]
+fn empty_document() {
+    let forgetful = |buf: &mut String| {
+        if buf.len() > 100 {
+            write_json::bool(buf, true);
+        }
+    };
+    let mut buf = String::new();
+    write_json::value_or_null(&mut buf, forgetful);
+    assert_eq!(buf, "null");
+
+    buf.clear();
+    write_json::value_or_null(&mut buf, |buf| write_json::number(buf, 1.0));
+    assert_eq!(buf, "1");
+
+    buf.clear();
+    let res = write_json::require_value(&mut buf, forgetful);
+    assert!(matches!(res, Err(write_json::Error::Empty)));
+    assert_eq!(buf, "");
+
+    write_json::require_value(&mut buf, |buf| {
+        write_json::array(buf);
+    })
+    .unwrap();
+    assert_eq!(buf, "[]");
+}
+
+#[test]
+fn number_from() {
+    let mut buf = String::new();
+    {
+        let mut obj = write_json::object(&mut buf);
+        obj.number_from("i32", -7_i32).number_from("u16", 65535_u16);
+        obj.array("f32").number_from(0.5_f32).number_from(u32::MAX);
+    }
+    check(&buf);
+    assert_eq!(buf, r#"{"i32":-7,"u16":65535,"f32":[0.5,4294967295]}"#);
+
+    buf.clear();
+    write_json::number_from(&mut buf, 3_u8);
+    assert_eq!(buf, "3");
+}
+
+#[test]
+fn object_header() {
+    let fmt = write_json::Formatter::new().object_header("_v", "1");
+    let mut buf = String::new();
+    {
+        let mut obj = fmt.object(&mut buf);
+        obj.string("name", "Peter");
+        obj.array("items").object().null("x");
+        obj.object("empty");
+    }
+    check(&buf);
+    assert_eq!(
+        buf,
+        r#"{"_v":1,"name":"Peter","items":[{"_v":1,"x":null}],"empty":{"_v":1}}"#
+    );
+
+    buf.clear();
+    fmt.pretty(1).object(&mut buf).bool("ok", true);
+    assert_eq!(buf, "{\n \"_v\": 1,\n \"ok\": true\n}");
+
+    buf.clear();
+    fmt.max_entries(0).object(&mut buf).bool("ok", true);
+    check(&buf);
+    assert_eq!(buf, r#"{"_v":1,"_truncated":true}"#);
+
+    buf.clear();
+    fmt.max_entries(1)
+        .skip_fields(|key| key.starts_with('_'))
+        .rename_keys(|key| key.to_uppercase().into())
+        .object(&mut buf)
+        .bool("ok", true)
+        .null("_hidden")
+        .null("more");
+    check(&buf);
+    assert_eq!(buf, r#"{"_v":1,"OK":true,"_truncated":true}"#);
+}
+
+#[test]
+fn check_balance() {
+    let fmt = write_json::Formatter::new().check_balance(true);
+    let mut buf = String::new();
+    {
+        let mut obj = fmt.object(&mut buf);
+        obj.string("tricky", "[{\"");
+        obj.array("a").object().array("b").null();
+    }
+    check(&buf);
+    assert_eq!(buf, r#"{"tricky":"[{\"","a":[{"b":[null]}]}"#);
+}
+
+#[test]
+#[cfg_attr(
+    debug_assertions,
+    should_panic(expected = "a container nested in this object was not closed")
+)]
+fn check_balance_forgotten() {
+    let fmt = write_json::Formatter::new().check_balance(true);
+    let mut buf = String::new();
+    let mut obj = fmt.object(&mut buf);
+    std::mem::forget(obj.array("leaked"));
+    obj.null("after");
+}
+
+#[test]
+fn bitset() {
+    let bits = [
+        true, false, true, true, false, false, false, true, // 0xb1
+        true, true, // 0xc0, padded
+    ];
+    let mut buf = String::new();
+    write_json::bitset(&mut buf, &bits);
+    check(&buf);
+    assert_eq!(buf, r#"{"len":10,"hex":"b1c0"}"#);
+
+    buf.clear();
+    {
+        let mut obj = write_json::object(&mut buf);
+        obj.bitset("flags", &[true; 8]).bitset("none", &[]);
+        obj.array("list").bitset(&[false, true]);
+    }
+    check(&buf);
+    assert_eq!(
+        buf,
+        r#"{"flags":{"len":8,"hex":"ff"},"none":{"len":0,"hex":""},"list":[{"len":2,"hex":"40"}]}"#
+    );
+}
+
+#[test]
+fn cidr() {
+    use std::net::{Ipv4Addr, Ipv6Addr};
+
+    let v4 = Ipv4Addr::new(10, 0, 0, 0);
+    let v6 = Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 0);
+    let mut buf = String::new();
+    {
+        let mut obj = write_json::object(&mut buf);
+        obj.cidr("v4", v4, 8).cidr("v6", v6, 32);
+        obj.cidr_object("v4_object", v4, 8);
+        obj.array("list")
+            .cidr(v6, 128)
+            .cidr_object(std::net::IpAddr::V6(v6), 48);
+    }
+    check(&buf);
+    assert_eq!(
+        buf,
+        concat!(
+            r#"{"v4":"10.0.0.0/8","v6":"2001:db8::/32","#,
+            r#""v4_object":{"addr":"10.0.0.0","prefix":8},"#,
+            r#""list":["2001:db8::/128",{"addr":"2001:db8::","prefix":48}]}"#,
+        )
+    );
+}
+
+#[test]
+#[should_panic(expected = "prefix /33 is too long for 10.0.0.0")]
+fn cidr_prefix_too_long() {
+    let mut buf = String::new();
+    write_json::array(&mut buf).cidr(std::net::Ipv4Addr::new(10, 0, 0, 0), 33);
+}
+
+#[test]
+fn number_sig() {
+    let mut buf = String::new();
+    {
+        let mut obj = write_json::object(&mut buf);
+        obj.number_sig("big", 123456.0, 3)
+            .number_sig("small", 0.00012345, 2);
+        obj.array("edge")
+            .number_sig(0.0, 3)
+            .number_sig(-2.5, 1)
+            .number_sig(-1234.5, 6)
+            .number_sig(999.9, 3)
+            .number_sig(1.5e-7, 2)
+            .number_sig(42.0, 0);
+    }
+    check(&buf);
+    assert_eq!(
+        buf,
+        r#"{"big":1.23e+5,"small":0.00012,"edge":[0.00,-2,-1234.50,1.00e+3,1.5e-7,4e+1]}"#
+    );
+}
+
+#[test]
+fn skip_fields() {
+    use std::cell::Cell;
+
+    let fmt = write_json::Formatter::new().skip_fields(|key| key.starts_with("secret"));
+    let mut buf = String::new();
+    let calls = Cell::new(0);
+    let expensive = || {
+        calls.set(calls.get() + 1);
+        42.0
+    };
+    {
+        let mut obj = fmt.object(&mut buf);
+        obj.string("secret_token", "hunter2")
+            .number("id", 1.0)
+            .number_with("secret_score", expensive)
+            .number_with("score", expensive);
+        obj.object("secret_nested").null("x");
+        obj.array("list").null();
+        obj.null("secret_last");
+    }
+    check(&buf);
+    assert_eq!(buf, r#"{"id":1,"score":42,"list":[null]}"#);
+    assert_eq!(calls.get(), 1);
+
+    buf.clear();
+    fmt.object(&mut buf).null("secret");
+    assert_eq!(buf, "{}");
+
+    buf.clear();
+    let fmt = fmt.max_entries(1);
+    {
+        let mut obj = fmt.object(&mut buf);
+        obj.null("secret").number("a", 1.0).null("secret");
+    }
+    assert_eq!(buf, r#"{"a":1}"#);
+
+    buf.clear();
+    {
+        let mut obj = fmt.object(&mut buf);
+        obj.number("a", 1.0).number_with("b", expensive);
+    }
+    assert_eq!(buf, r#"{"a":1,"_truncated":true}"#);
+    assert_eq!(calls.get(), 1);
+}
+
+#[test]
+fn record_ranges() {
+    let mut buf = String::new();
+    let mut ranges = Vec::new();
+    {
+        let mut obj = write_json::Formatter::new().pretty(1).object(&mut buf);
+        obj.record_ranges(&mut ranges).number("id", 1.0);
+        let mut a = obj.object("a/b");
+        a.array("list").string("x").null();
+        a.string("tricky\u{1F600}~", "],}");
+    }
+    let pointers: Vec<&str> = ranges.iter().map(|(p, _)| p.as_str()).collect();
+    assert_eq!(
+        pointers,
+        [
+            "",
+            "/id",
+            "/a~1b",
+            "/a~1b/list",
+            "/a~1b/list/0",
+            "/a~1b/list/1",
+            "/a~1b/tricky\u{1F600}~0",
+        ]
+    );
+    let text = |pointer: &str| {
+        let (_, range) = ranges.iter().find(|(p, _)| p == pointer).unwrap();
+        &buf[range.clone()]
+    };
+    assert_eq!(text(""), buf);
+    assert_eq!(text("/id"), "1");
+    assert_eq!(text("/a~1b/list"), "[\n   \"x\",\n   null\n  ]");
+    assert_eq!(text("/a~1b/list/1"), "null");
+    assert_eq!(text("/a~1b/tricky\u{1F600}~0"), r#""],}""#);
+
+    let mut buf = String::new();
+    let mut ranges = Vec::new();
+    {
+        let fmt = write_json::Formatter::new()
+            .max_entries(1)
+            .skip_fields(|key| key == "b");
+        let mut arr = fmt.array(&mut buf);
+        arr.record_ranges(&mut ranges);
+        arr.object()
+            .number("a", 1.0)
+            .number("b", 2.0)
+            .number("c", 3.0);
+        arr.array().number(4.0);
+    }
+    assert_eq!(buf, r#"[{"a":1,"_truncated":true}]"#);
+    assert_eq!(
+        ranges,
+        [
+            ("".to_string(), 0..buf.len()),
+            ("/0".to_string(), 1..26),
+            ("/0/a".to_string(), 6..7),
+        ]
+    );
+}
+
+#[test]
+fn duration_iso8601() {
+    use std::time::Duration;
+
+    let mut buf = String::new();
+    write_json::duration_iso8601(&mut buf, Duration::from_millis(90_500));
+    assert_eq!(buf, r#""PT1M30.5S""#);
+
+    buf.clear();
+    {
+        let mut obj = write_json::object(&mut buf);
+        obj.duration_iso8601("zero", Duration::ZERO);
+        obj.array("list")
+            .duration_iso8601(Duration::from_secs(3600))
+            .duration_iso8601(Duration::from_secs(7322))
+            .duration_iso8601(Duration::from_nanos(1))
+            .duration_iso8601(Duration::new(60, 250_000_000));
+    }
+    check(&buf);
+    assert_eq!(
+        buf,
+        r#"{"zero":"PT0S","list":["PT1H","PT2H2M2S","PT0.000000001S","PT1M0.25S"]}"#
+    );
+}
+
+#[test]
+fn json_lines() {
+    let mut buf = String::new();
+    let mut lines = write_json::json_lines(&mut buf);
+    lines.record(|buf| {
+        write_json::object(buf).number("id", 1.0);
+    });
+    let res = lines.try_record(|buf| {
+        let mut obj = write_json::object(buf);
+        obj.number("id", 2.0).string("note", "half-written");
+        obj.array("values").number(1.0).try_number(f64::NAN)?;
+        Ok::<(), write_json::Error>(())
+    });
+    assert!(matches!(res, Err(write_json::Error::NonFinite(_))));
+    lines
+        .try_record(|buf| {
+            write_json::object(buf).try_number("id", 3.0)?;
+            Ok::<(), write_json::Error>(())
+        })
+        .unwrap();
+    assert_eq!(buf, "{\"id\":1}\n{\"id\":3}\n");
+    for line in buf.lines() {
+        check(line);
+    }
+}
+
+#[test]
+fn strip_controls() {
+    let fmt = write_json::Formatter::new().strip_controls(true);
+    let mut buf = String::new();
+    fmt.string(&mut buf, "a\tb\nc\r\nd\0e\u{1b}[0m\"\\");
+    check(&buf);
+    assert_eq!(buf, r#""a b c  de[0m\"\\""#);
+
+    buf.clear();
+    fmt.object(&mut buf).string("log\n", "line\u{7}");
+    assert_eq!(buf, r#"{"log ":"line"}"#);
+}
+
+#[test]
+fn index_comments() {
+    let fmt = write_json::Formatter::new()
+        .pretty(2)
+        .elements_per_line(50)
+        .index_comments(100);
+    let mut buf = String::new();
+    {
+        let mut arr = fmt.array(&mut buf);
+        for i in 0..250 {
+            arr.number(i as f64);
+        }
+    }
+    let comments: Vec<&str> = buf
+        .lines()
+        .filter(|line| line.trim_start().starts_with("//"))
+        .collect();
+    assert_eq!(
+        comments,
+        [
+            "  // items 0-99",
+            "  // items 100-199",
+            "  // items 200-249"
+        ]
+    );
+    assert!(buf.contains("99,\n  // items 0-99\n  100, 101"));
+    assert!(buf.ends_with("249\n  // items 200-249\n]"));
+
+    let stripped: String = buf
+        .lines()
+        .filter(|line| !line.trim_start().starts_with("//"))
+        .collect();
+    check(&stripped);
+
+    buf.clear();
+    fmt.array(&mut buf);
+    assert_eq!(buf, "[]");
+
+    buf.clear();
+    write_json::Formatter::new()
+        .index_comments(1)
+        .array(&mut buf)
+        .null();
+    assert_eq!(buf, "[null]");
+}
+
+#[test]
+fn crc32() {
+    use std::hash::Hasher;
+    use write_json::Crc32;
+
+    let mut check_value = Crc32::default();
+    check_value.write(b"123456789");
+    assert_eq!(check_value.finish(), 0xCBF43926);
+
+    let mut buf = String::new();
+    let crc = {
+        let mut obj = write_json::hashing_object(&mut buf, Crc32::default());
+        obj.string("payload", "\u{1F600}\n").number("seq", 7.0);
+        obj.array("list").null().object().bool("deep", true);
+        obj.null("end");
+        obj.finish()
+    };
+    check(&buf);
+    let mut expected = Crc32::default();
+    expected.write(buf.as_bytes());
+    assert_eq!(crc, expected.finish());
+    assert!(crc <= u64::from(u32::MAX));
+}
+
+#[test]
+fn flat() {
+    let mut buf = String::new();
+    {
+        let mut obj = write_json::Formatter::new().flat().object(&mut buf);
+        obj.string("level", "info");
+        obj.object("ctx")
+            .number("id", 1.0)
+            .array("tags")
+            .string("a");
+        obj.null("end");
+    }
+    check(&buf);
+    assert_eq!(
+        buf,
+        "{\n\"level\": \"info\",\n\"ctx\": {\n\"id\": 1,\n\"tags\": [\n\"a\"\n]\n},\n\"end\": null\n}"
+    );
+}
+
+#[test]
+fn char_range() {
+    let mut buf = String::new();
+    write_json::array(&mut buf)
+        .char_range('a'..='c', 10)
+        .unwrap();
+    check(&buf);
+    assert_eq!(buf, "[97,98,99]");
+
+    buf.clear();
+    {
+        let mut arr = write_json::array(&mut buf);
+        let res = arr.char_range('\0'..=char::MAX, 1000);
+        assert!(matches!(res, Err(write_json::Error::TooLarge(1_112_064))));
+        arr.char_range('\u{D7FF}'..='\u{E000}', 2).unwrap();
+        arr.char_range('z'..='a', 0).unwrap();
+    }
+    assert_eq!(buf, "[55295,57344]");
+}
+
+#[cfg(feature = "stats")]
+#[test]
+fn escape_stats() {
+    write_json::reset_escape_stats();
+    let mut buf = String::new();
+    write_json::array(&mut buf)
+        .string("clean")
+        .string("also clean")
+        .string("tab\there")
+        .string("\"quoted\"\n");
+    check(&buf);
+    let stats = write_json::escape_stats();
+    assert_eq!(stats.fast_hits, 2);
+    assert_eq!(stats.slow_hits, 2);
+    assert_eq!(stats.bytes_escaped, 17);
+
+    write_json::reset_escape_stats();
+    buf.clear();
+    write_json::object(&mut buf)
+        .string("a", "b")
+        .string("key\n", "c");
+    let stats = write_json::escape_stats();
+    assert_eq!(stats.fast_hits, 3);
+    assert_eq!(stats.slow_hits, 1);
+    assert_eq!(stats.bytes_escaped, 4);
+}
+
+#[test]
+fn schema() {
+    let mut buf = String::new();
+    write_json::object(&mut buf)
+        .schema("https://json-schema.org/draft/2020-12/schema")
+        .string("type", "object");
+    check(&buf);
+    assert_eq!(
+        buf,
+        r#"{"$schema":"https://json-schema.org/draft/2020-12/schema","type":"object"}"#
+    );
+
+    buf.clear();
+    write_json::object(&mut buf).schema("urn:\"x\"");
+    assert_eq!(buf, r#"{"$schema":"urn:\"x\""}"#);
+}
+
+#[test]
+fn to_query_param() {
+    let param = write_json::to_query_param(|buf| {
+        write_json::object(buf)
+            .string("q", "a b&c")
+            .number("n", 1.5)
+            .string("u", "é");
+    });
+    assert_eq!(
+        param,
+        "%7B%22q%22%3A%22a%20b%26c%22%2C%22n%22%3A1.5%2C%22u%22%3A%22%C3%A9%22%7D"
+    );
+}
+
+#[test]
+fn plus_sign() {
+    let fmt = write_json::Formatter::new().plus_sign(true);
+    let mut buf = String::new();
+    fmt.array(&mut buf)
+        .number(5.0)
+        .number(-5.0)
+        .number(0.0)
+        .number(-0.0)
+        .number(1.5)
+        .number(f64::NAN)
+        .number(f64::INFINITY);
+    assert_eq!(buf, "[+5,-5,+0,-0,+1.5,NaN,inf]");
+
+    buf.clear();
+    fmt.non_finite_tokens(true)
+        .array(&mut buf)
+        .number(f64::INFINITY)
+        .number(f64::NEG_INFINITY)
+        .number(1.0);
+    assert_eq!(buf, "[Infinity,-Infinity,+1]");
+}
+
+#[test]
+fn with_buffer() {
+    let mut buf = String::from("stale");
+    let mut outputs = Vec::new();
+    for name in ["a", "bb", "ccc"] {
+        let json = write_json::with_buffer(&mut buf, |buf| {
+            write_json::object(buf).string("name", name);
+        });
+        check(json);
+        outputs.push(json.to_string());
+    }
+    assert_eq!(
+        outputs,
+        [r#"{"name":"a"}"#, r#"{"name":"bb"}"#, r#"{"name":"ccc"}"#]
+    );
+}
+
+#[test]
+fn int_radix() {
+    let mut buf = String::new();
+    write_json::object(&mut buf)
+        .int_radix("hex", 255, 16, "0x")
+        .int_radix("bin", 10, 2, "0b")
+        .int_radix("neg", -255, 16, "0x")
+        .int_radix("zero", 0, 36, "");
+    check(&buf);
+    assert_eq!(
+        buf,
+        r#"{"hex":"0xff","bin":"0b1010","neg":"-0xff","zero":"0"}"#
+    );
+
+    buf.clear();
+    write_json::array(&mut buf).int_radix(i64::MIN, 2, "");
+    assert_eq!(buf, format!("[\"-1{}\"]", "0".repeat(63)));
+}
+
+#[test]
+fn first_some() {
+    let enc = |buf: &mut String, port: &u16| write_json::number_from(buf, *port);
+    let mut buf = String::new();
+    write_json::object(&mut buf)
+        .first_some("port", &[None, Some(8080), Some(80)], false, enc)
+        .first_some("missing", &[None::<u16>, None], true, enc)
+        .first_some("omitted", &[None::<u16>], false, enc)
+        .first_some("empty", &[], true, enc);
+    check(&buf);
+    assert_eq!(buf, r#"{"port":8080,"missing":null,"empty":null}"#);
+}
+
+#[test]
+fn objects_with_keys() {
+    let keys = [
+        write_json::PreparedKey::new("x"),
+        write_json::PreparedKey::new("y\n"),
+    ];
+    let rows = [[1.0, 2.0], [3.0, 4.0]];
+    let mut buf = String::new();
+    write_json::array(&mut buf)
+        .objects_with_keys(&keys, rows, |buf, row, i| write_json::number(buf, row[i]))
+        .objects_with_keys(&keys[..0], [()], |_, _, _| ());
+    check(&buf);
+    assert_eq!(buf, r#"[{"x":1,"y\n":2},{"x":3,"y\n":4},{}]"#);
+}
+
+#[test]
+fn date() {
+    let mut buf = String::new();
+    write_json::object(&mut buf)
+        .date("day", 2024, 1, 15)
+        .date("old", 987, 12, 3)
+        .date("leap", 2000, 2, 29);
+    check(&buf);
+    assert_eq!(
+        buf,
+        r#"{"day":"2024-01-15","old":"0987-12-03","leap":"2000-02-29"}"#
+    );
+}
+
+#[test]
+#[cfg_attr(debug_assertions, should_panic(expected = "invalid date 1900-02-29"))]
+fn date_invalid() {
+    let mut buf = String::new();
+    write_json::array(&mut buf).date(1900, 2, 29);
+}
+
+#[test]
+fn nested_with() {
+    let mut buf = String::new();
+    write_json::object(&mut buf)
+        .object_with("a", |a| {
+            a.object_with("b", |b| {
+                b.string("c", "1");
+            })
+            .array_with("list", |list| {
+                list.object_with(|obj| {
+                    obj.bool("ok", true);
+                })
+                .array_with(|_| ());
+            });
+        })
+        .number("n", 2.0);
+    check(&buf);
+    assert_eq!(
+        buf,
+        r#"{"a":{"b":{"c":"1"},"list":[{"ok":true},[]]},"n":2}"#
+    );
+}
+
+#[test]
+fn nested_done() {
+    let mut buf = String::new();
+    write_json::object(&mut buf)
+        .nested("a")
+        .string("x", "1")
+        .nested("b")
+        .nested("c")
+        .bool("deep", true)
+        .done()
+        .fields(|b| {
+            b.array("list").number(1.0);
+        })
+        .done()
+        .null("y")
+        .done()
+        .number("n", 2.0)
+        .nested("dropped")
+        .number("z", 3.0);
+    check(&buf);
+    assert_eq!(
+        buf,
+        r#"{"a":{"x":"1","b":{"c":{"deep":true},"list":[1]},"y":null},"n":2,"dropped":{"z":3}}"#
+    );
+
+    buf.clear();
+    write_json::Formatter::new()
+        .pretty(1)
+        .max_entries(1)
+        .object(&mut buf)
+        .nested("a")
+        .null("x")
+        .null("cut")
+        .done()
+        .null("b");
+    check(&buf);
+    assert_eq!(
+        buf,
+        "{\n \"a\": {\n  \"x\": null,\n  \"_truncated\": true\n },\n \"_truncated\": true\n}"
+    );
+}
+
+#[test]
+fn big_as_strings() {
+    let fmt = write_json::Formatter::new().big_as_strings(true);
+    let mut buf = String::new();
+    fmt.array(&mut buf)
+        .number(9007199254740991.0)
+        .number(-9007199254740991.0)
+        .number(9007199254740992.0)
+        .number(-18446744073709552000.0)
+        .number(1.5);
+    check(&buf);
+    assert_eq!(
+        buf,
+        r#"[9007199254740991,-9007199254740991,"9007199254740992","-18446744073709552000",1.5]"#
+    );
+}
+
+#[test]
+fn record_omitted() {
+    let fmt = write_json::Formatter::new().skip_fields(|key| key.starts_with("secret"));
+    let mut buf = String::new();
+    let mut omitted = Vec::new();
+    {
+        let mut obj = fmt.object(&mut buf);
+        obj.record_omitted(&mut omitted)
+            .string("secret_token", "hunter2")
+            .number("id", 1.0)
+            .first_some("port", &[None::<u16>], false, |_, _| ())
+            .first_some("nulled", &[None::<u16>], true, |_, _| ());
+        obj.object("nested").null("secret_inner");
+    }
+    check(&buf);
+    assert_eq!(buf, r#"{"id":1,"nulled":null,"nested":{}}"#);
+    assert_eq!(omitted, ["secret_token", "port"]);
+}
+
+#[test]
+fn to_boxed_str() {
+    let json: Box<str> = write_json::to_boxed_str(|buf| {
+        write_json::array(buf).number(1.0).string("two");
+    });
+    check(&json);
+    assert_eq!(&*json, r#"[1,"two"]"#);
+    assert_eq!(String::from(json).capacity(), 9);
+}
+
+#[test]
+fn prepared_keys() {
+    fn tree(
+        obj: &mut write_json::Object<'_>,
+        depth: usize,
+        keys: &mut Option<write_json::PreparedKeys<'_>>,
+    ) {
+        match keys {
+            Some(keys) => {
+                obj.field_prepared(keys.get("node\tname"), depth as f64, write_json::number)
+            }
+            None => obj.number("node\tname", depth as f64),
+        };
+        if depth > 0 {
+            let mut children = obj.array("children");
+            for _ in 0..2 {
+                tree(&mut children.object(), depth - 1, keys);
+            }
+        }
+    }
+
+    let mut plain = String::new();
+    tree(&mut write_json::object(&mut plain), 3, &mut None);
+    let mut cached = String::new();
+    let mut keys = Some(write_json::PreparedKeys::new(write_json::Formatter::new()));
+    tree(&mut write_json::object(&mut cached), 3, &mut keys);
+    check(&cached);
+    assert_eq!(cached, plain);
+    assert!(cached.starts_with(r#"{"node\tname":3,"children":[{"node\tname":2,"#));
+}
+
+#[test]
+fn string_preescaped() {
+    let mut buf = String::new();
+    write_json::object(&mut buf)
+        .string_preescaped("s", r#"a\"b\né\\"#)
+        .array("list")
+        .string_preescaped("")
+        .string_preescaped("plain");
+    check(&buf);
+    assert_eq!(buf, r#"{"s":"a\"b\né\\","list":["","plain"]}"#);
+}
+
+#[test]
+#[cfg_attr(debug_assertions, should_panic(expected = "string is not escaped"))]
+fn string_preescaped_invalid() {
+    let mut buf = String::new();
+    write_json::array(&mut buf).string_preescaped("a\"b");
+}
+
+#[test]
+fn object_if() {
+    let mut buf = String::new();
+    {
+        let mut obj = write_json::object(&mut buf);
+        assert!(obj.object_if("first", false).is_none());
+        obj.number("a", 1.0);
+        if let Some(mut extra) = obj.object_if("extra", true) {
+            extra.bool("on", true);
+        }
+        assert!(obj.object_if("absent", false).is_none());
+        obj.number("b", 2.0);
+    }
+    check(&buf);
+    assert_eq!(buf, r#"{"a":1,"extra":{"on":true},"b":2}"#);
+
+    buf.clear();
+    {
+        let mut obj = write_json::object(&mut buf);
+        obj.object_if("first", true).unwrap().null("x");
+        obj.number("a", 1.0);
+    }
+    check(&buf);
+    assert_eq!(buf, r#"{"first":{"x":null},"a":1}"#);
+}
+
+#[test]
+fn coordinates() {
+    let ring = [
+        [100.0, 0.0],
+        [101.0, 0.0],
+        [101.0, 1.0],
+        [100.0, 1.0],
+        [100.0, 0.0],
+    ];
+    let mut buf = String::new();
+    {
+        let mut obj = write_json::object(&mut buf);
+        obj.position("point", [-122.41941551, 37.7749295], 7)
+            .coordinates("ring", &ring, 7);
+        obj.array("polygon")
+            .coordinates(&ring[..2], 1)
+            .coordinates(&[], 1);
+    }
+    check(&buf);
+    assert_eq!(
+        buf,
+        concat!(
+            r#"{"point":[-122.4194155,37.7749295],"#,
+            r#""ring":[[100.0000000,0.0000000],[101.0000000,0.0000000],"#,
+            r#"[101.0000000,1.0000000],[100.0000000,1.0000000],[100.0000000,0.0000000]],"#,
+            r#""polygon":[[[100.0,0.0],[101.0,0.0]],[]]}"#,
+        )
+    );
+}
+
+#[test]
+fn streaming_array() {
+    use std::ops::ControlFlow;
+
+    let mut out = String::new();
+    let mut chunks = 0;
+    let mut buf = String::new();
+    {
+        let mut arr = write_json::streaming_array(&mut buf, Some(8), |chunk| {
+            chunks += 1;
+            out.push_str(chunk);
+            if chunks == 2 {
+                ControlFlow::Break(())
+            } else {
+                ControlFlow::Continue(())
+            }
+        });
+        let mut written = 0;
+        while arr.number(written as f64 * 1000.0).is_continue() {
+            written += 1;
+        }
+        assert!(arr.is_paused());
+        assert!(arr.string("dropped").is_break());
+        assert!(arr
+            .write(|arr| {
+                arr.string("dropped");
+            })
+            .is_break());
+        assert_eq!(written, 4);
+        arr.resume();
+        assert!(arr.null().is_continue());
+        arr.finish();
+    }
+    assert_eq!(chunks, 3);
+    assert_eq!(buf, "");
+    check(&out);
+    assert_eq!(out, "[0,1000,2000,3000,4000,null]");
+}
+
+#[test]
+fn percent() {
+    let mut buf = String::new();
+    write_json::array(&mut buf)
+        .percent(0.5, 2)
+        .percent(0.1234, 2)
+        .percent(-0.25, 0)
+        .percent(0.12345, 1)
+        .percent(-0.0001, 1)
+        .percent(1.0, 0);
+    check(&buf);
+    assert_eq!(buf, r#"["50.00%","12.34%","-25%","12.3%","0.0%","100%"]"#);
+}
+
+#[test]
+fn check_size() {
+    let write = |buf: &mut String| {
+        write_json::object(buf).string("name", "Peter");
+    };
+    let mut buf = String::from("prefix ");
+    let res = write_json::check_size(&mut buf, 15, write);
+    assert!(matches!(res, Err(write_json::Error::SizeLimitExceeded(16))));
+    assert_eq!(buf, "prefix ");
+
+    write_json::check_size(&mut buf, 16, write).unwrap();
+    assert_eq!(buf, r#"prefix {"name":"Peter"}"#);
+}
+
+#[test]
+fn max_bytes() {
+    use write_json::Error;
+
+    let fmt = write_json::Formatter::new().max_bytes(20);
+    let mut buf = String::new();
+    let mut obj = fmt.object(&mut buf);
+    obj.number("a", 1.0).string("b", "xxxx").number("c", 3.0);
+    obj.number_with("d", || unreachable!());
+    let res = obj.finish();
+    assert!(matches!(res, Err(Error::SizeLimitExceeded(23))));
+    check(&buf);
+    assert_eq!(buf, r#"{"a":1,"b":"xxxx","_truncated":true}"#);
+
+    buf.clear();
+    let mut arr = fmt.max_bytes(6).array(&mut buf);
+    arr.number(1.0).number(2.0).number(3.0).number(4.0);
+    assert!(matches!(arr.finish(), Err(Error::SizeLimitExceeded(8))));
+    assert_eq!(buf, "[1,2,3]");
+
+    buf.clear();
+    let mut arr = fmt.max_bytes(6).array(&mut buf);
+    arr.number(1.0).number(2.0);
+    arr.finish().unwrap();
+    assert_eq!(buf, "[1,2]");
+
+    buf.clear();
+    let mut obj = fmt.object(&mut buf);
+    obj.array_of("list", 0..20, |buf, i| write_json::number(buf, i.into()));
+    assert!(obj.finish().is_err());
+    check(&buf);
+    assert_eq!(buf, r#"{"_truncated":true}"#);
+
+    buf.clear();
+    fmt.object(&mut buf)
+        .number("a", 1.0)
+        .array_of("list", 0..20, |buf, i| write_json::number(buf, i.into()));
+    check(&buf);
+    assert_eq!(buf, r#"{"a":1,"_truncated":true}"#);
+}
+
+#[test]
+fn object_of_arrays() {
+    let groups =
+        std::collections::BTreeMap::from([("admins", vec!["ann", "bob"]), ("guests", vec![])]);
+    let mut buf = String::new();
+    write_json::object_of_arrays(&mut buf, &groups, |arr, name| {
+        arr.string(name);
+    });
+    check(&buf);
+    assert_eq!(buf, r#"{"admins":["ann","bob"],"guests":[]}"#);
+}
+
+#[test]
+fn expand_tabs() {
+    let fmt = write_json::Formatter::new().expand_tabs(2);
+    let mut buf = String::new();
+    fmt.object(&mut buf)
+        .string("a\tb", "\tx\t\ty\n")
+        .string_chars("c", "1\t2".chars());
+    check(&buf);
+    assert_eq!(buf, r#"{"a  b":"  x    y\n","c":"1  2"}"#);
+
+    buf.clear();
+    let fmt = fmt.strip_controls(true).expand_tabs(0);
+    fmt.string(&mut buf, "a\tb\n");
+    assert_eq!(buf, r#""ab ""#);
+}
+
+#[test]
+fn field_from_scratch() {
+    let mut scratch = String::new();
+    write_json::array(&mut scratch).number(1.0).string("]");
+    let mut buf = String::new();
+    write_json::object(&mut buf)
+        .field_from_scratch("list", &scratch)
+        .bool("ok", true);
+    check(&buf);
+    assert_eq!(buf, r#"{"list":[1,"]"],"ok":true}"#);
+}
+
+#[test]
+fn raw_passthrough() {
+    // As received, say in a `RawValue`: the bytes must come out unchanged.
+    let raw = "{ \"b\" : [1.50, \"\\u00e9\"] }";
+    let mut buf = String::new();
+    write_json::object(&mut buf)
+        .field_from_scratch("payload", raw)
+        .array("list")
+        .element_from_scratch(raw)
+        .element_from_scratch("1e2");
+    check(&buf);
+    assert_eq!(buf, format!(r#"{{"payload":{raw},"list":[{raw},1e2]}}"#));
+}
+
+#[test]
+#[cfg_attr(
+    debug_assertions,
+    should_panic(expected = "scratch is not a JSON value")
+)]
+fn field_from_scratch_unbalanced() {
+    let mut buf = String::new();
+    write_json::object(&mut buf).field_from_scratch("list", "[1,2");
+}
+
+#[test]
+fn prune_nulls() {
+    let pruned = write_json::prune_nulls(
+        r#"{"a":null,"b":1,"c":null,"d":{"e":null},"f":[null,{"g":null,"h":"\":null"}],"i":null}"#,
+    );
+    check(&pruned);
+    assert_eq!(pruned, r#"{"b":1,"d":{},"f":[null,{"h":"\":null"}]}"#);
+
+    let pretty = "{\n  \"a\": null,\n  \"b\": [\n    1\n  ],\n  \"\\\"c\": null\n}";
+    assert_eq!(
+        write_json::prune_nulls(pretty),
+        "{\n  \"b\": [\n    1\n  ]\n}"
+    );
+    assert_eq!(
+        write_json::prune_nulls(r#"{"null":"null"}"#),
+        r#"{"null":"null"}"#
+    );
+    assert_eq!(write_json::prune_nulls("null"), "null");
+}
+
+#[test]
+fn number_f16() {
+    let mut buf = String::new();
+    write_json::array(&mut buf)
+        .number_f16(0x2E66) // 0.0999755859375
+        .number_f16(0x3555) // 0.333251953125
+        .number_f16(0xC100)
+        .number_f16(0x7BFF) // 65504, the largest
+        .number_f16(0x0001) // 2^-24, the smallest
+        .number_f16(0x8000)
+        .number_bf16(0x3DCD) // 0.10009765625
+        .number_bf16(0x3F80)
+        .number_bf16(0x4049); // 3.140625
+    check(&buf);
+    assert_eq!(buf, "[0.1,0.3333,-2.5,65500,0.00000006,-0,0.1,1,3.14]");
+}
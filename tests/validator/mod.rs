@@ -0,0 +1,183 @@
+//! A minimal recursive-descent JSON validator, used to check that everything
+//! the tests produce is syntactically valid.
+
+/// Panics if `json` is not a single valid JSON document.
+#[track_caller]
+pub fn check(json: &str) {
+    if let Err(err) = validate(json) {
+        panic!("invalid JSON: {err}\n{json}")
+    }
+}
+
+/// Validates `json` per RFC 8259.
+pub fn validate(json: &str) -> Result<(), String> {
+    Validator {
+        bytes: json.as_bytes(),
+        pos: 0,
+        strict: true,
+    }
+    .document()
+}
+
+/// Like [`validate`], but tolerates trailing commas in arrays and objects.
+#[allow(unused)]
+pub fn validate_lenient(json: &str) -> Result<(), String> {
+    Validator {
+        bytes: json.as_bytes(),
+        pos: 0,
+        strict: false,
+    }
+    .document()
+}
+
+struct Validator<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+    strict: bool,
+}
+
+impl Validator<'_> {
+    fn document(mut self) -> Result<(), String> {
+        self.value()?;
+        self.ws();
+        if self.pos != self.bytes.len() {
+            return self.error("trailing characters");
+        }
+        Ok(())
+    }
+
+    fn value(&mut self) -> Result<(), String> {
+        self.ws();
+        match self.peek() {
+            Some(b'{') => self.container(b'}', true),
+            Some(b'[') => self.container(b']', false),
+            Some(b'"') => self.string(),
+            Some(b't') => self.literal("true"),
+            Some(b'f') => self.literal("false"),
+            Some(b'n') => self.literal("null"),
+            Some(b'-' | b'0'..=b'9') => self.number(),
+            _ => self.error("expected a value"),
+        }
+    }
+
+    fn container(&mut self, close: u8, is_object: bool) -> Result<(), String> {
+        self.pos += 1;
+        self.ws();
+        if self.eat(close) {
+            return Ok(());
+        }
+        loop {
+            if is_object {
+                self.ws();
+                if self.peek() != Some(b'"') {
+                    return self.error("expected a key");
+                }
+                self.string()?;
+                self.ws();
+                if !self.eat(b':') {
+                    return self.error("expected `:`");
+                }
+            }
+            self.value()?;
+            self.ws();
+            if self.eat(close) {
+                return Ok(());
+            }
+            if !self.eat(b',') {
+                return self.error("expected `,` or a closing bracket");
+            }
+            self.ws();
+            if self.peek() == Some(close) {
+                if self.strict {
+                    return self.error("trailing comma");
+                }
+                self.pos += 1;
+                return Ok(());
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<(), String> {
+        self.pos += 1;
+        loop {
+            match self.next() {
+                None => return self.error("unterminated string"),
+                Some(b'"') => return Ok(()),
+                Some(b'\\') => match self.next() {
+                    Some(b'"' | b'\\' | b'/' | b'b' | b'f' | b'n' | b'r' | b't') => (),
+                    Some(b'u') => {
+                        for _ in 0..4 {
+                            if !self.next().is_some_and(|b| b.is_ascii_hexdigit()) {
+                                return self.error("bad unicode escape");
+                            }
+                        }
+                    }
+                    _ => return self.error("bad escape"),
+                },
+                Some(0..=0x1F) => return self.error("unescaped control character"),
+                Some(_) => (),
+            }
+        }
+    }
+
+    fn number(&mut self) -> Result<(), String> {
+        self.eat(b'-');
+        if !self.eat(b'0') && self.digits() == 0 {
+            return self.error("expected digits");
+        }
+        if self.eat(b'.') && self.digits() == 0 {
+            return self.error("expected fraction digits");
+        }
+        if self.eat(b'e') || self.eat(b'E') {
+            let _ = self.eat(b'+') || self.eat(b'-');
+            if self.digits() == 0 {
+                return self.error("expected exponent digits");
+            }
+        }
+        Ok(())
+    }
+
+    fn digits(&mut self) -> usize {
+        let start = self.pos;
+        while self.peek().is_some_and(|b| b.is_ascii_digit()) {
+            self.pos += 1;
+        }
+        self.pos - start
+    }
+
+    fn literal(&mut self, text: &str) -> Result<(), String> {
+        if !self.bytes[self.pos..].starts_with(text.as_bytes()) {
+            return self.error("bad literal");
+        }
+        self.pos += text.len();
+        Ok(())
+    }
+
+    fn ws(&mut self) {
+        while matches!(self.peek(), Some(b' ' | b'\t' | b'\n' | b'\r')) {
+            self.pos += 1;
+        }
+    }
+
+    fn eat(&mut self, b: u8) -> bool {
+        let res = self.peek() == Some(b);
+        if res {
+            self.pos += 1;
+        }
+        res
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.bytes.get(self.pos).copied()
+    }
+
+    fn next(&mut self) -> Option<u8> {
+        let res = self.peek();
+        self.pos += 1;
+        res
+    }
+
+    fn error<T>(&self, msg: &str) -> Result<T, String> {
+        Err(format!("{msg} at byte {}", self.pos))
+    }
+}
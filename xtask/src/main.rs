@@ -15,11 +15,13 @@ fn main() -> xshell::Result<()> {
     {
         let _s = section("BUILD");
         cmd!(sh, "cargo test --workspace --no-run").run()?;
+        cmd!(sh, "cargo test --workspace --all-features --no-run").run()?;
     }
 
     {
         let _s = section("TEST");
         cmd!(sh, "cargo test --workspace -- --nocapture").run()?;
+        cmd!(sh, "cargo test --workspace --all-features -- --nocapture").run()?;
     }
 
     {
@@ -37,7 +39,7 @@ fn main() -> xshell::Result<()> {
         let tag_exists = cmd!(sh, "git tag --list")
             .read()?
             .split_ascii_whitespace()
-            .any(|it| it == &tag);
+            .any(|it| it == tag);
 
         if current_branch == "master" && !tag_exists {
             cmd!(sh, "git tag v{version}").run()?;